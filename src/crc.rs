@@ -0,0 +1,70 @@
+//! CRC-16 shared by the protocol modules.
+
+/// A CRC-16 variant. Reflected variants shift right and take `poly` bit-reversed.
+#[derive(Copy, Clone)]
+pub struct Crc16 {
+    pub poly: u16,
+    pub init: u16,
+    pub reflected: bool,
+}
+
+/// CRC-16/CCITT-FALSE (polynomial 0x1021, initial value 0xFFFF), used by `framing`
+pub const CCITT_FALSE: Crc16 = Crc16 {
+    poly: 0x1021,
+    init: 0xFFFF,
+    reflected: false,
+};
+
+/// CRC-16/XMODEM (polynomial 0x1021, initial value 0), used by `xmodem`
+pub const XMODEM: Crc16 = Crc16 {
+    poly: 0x1021,
+    init: 0x0000,
+    reflected: false,
+};
+
+/// CRC-16/MODBUS (polynomial 0x8005 reflected, initial value 0xFFFF), used by `modbus`
+pub const MODBUS: Crc16 = Crc16 {
+    poly: 0xA001,
+    init: 0xFFFF,
+    reflected: true,
+};
+
+impl Crc16 {
+    pub fn checksum(&self, data: &[u8]) -> u16 {
+        let mut crc = self.init;
+
+        for byte in data {
+            if self.reflected {
+                crc ^= *byte as u16;
+                for _ in 0..8 {
+                    crc = if crc & 0x0001 != 0 { (crc >> 1) ^ self.poly } else { crc >> 1 };
+                }
+            } else {
+                crc ^= (*byte as u16) << 8;
+                for _ in 0..8 {
+                    crc = if crc & 0x8000 != 0 { (crc << 1) ^ self.poly } else { crc << 1 };
+                }
+            }
+        }
+
+        crc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_values() {
+        assert_eq!(CCITT_FALSE.checksum(b"123456789"), 0x29B1);
+        assert_eq!(XMODEM.checksum(b"123456789"), 0x31C3);
+        assert_eq!(MODBUS.checksum(b"123456789"), 0x4B37);
+    }
+
+    #[test]
+    fn empty_data_gives_initial_value() {
+        assert_eq!(CCITT_FALSE.checksum(&[]), 0xFFFF);
+        assert_eq!(XMODEM.checksum(&[]), 0x0000);
+    }
+}
@@ -0,0 +1,311 @@
+use core::mem::ManuallyDrop;
+use core::ptr;
+use core::sync::atomic::{compiler_fence, Ordering};
+
+use max32660_pac::dma::CH;
+
+/// Channel registers start at 0x100 and repeat every 0x20 bytes. The PAC's `ch0()`..`ch3()`
+/// accessors add the 0x100 twice, so channels are addressed from the DMA base here instead.
+const CHANNEL_STRIDE: usize = 0x20;
+
+/// DMA request lines, see `DMA_CFG.REQSEL`
+#[derive(Copy, Clone)]
+pub enum Request {
+    MemToMem = 0,
+    Spi0Rx = 1,
+    Spi1Rx = 2,
+    Uart0Rx = 4,
+    Uart1Rx = 5,
+    I2c0Rx = 7,
+    I2c1Rx = 8,
+    Spi0Tx = 33,
+    Spi1Tx = 34,
+    Uart0Tx = 36,
+    Uart1Tx = 37,
+    I2c0Tx = 39,
+    I2c1Tx = 40,
+}
+
+#[derive(Debug)]
+pub enum DmaError {
+    /// The circular buffer was overwritten before it was read
+    Overrun,
+}
+
+/// A peripheral whose DMA requests are turned off when its transfer ends
+pub trait DmaPeripheral {
+    /// Stops the peripheral raising DMA requests
+    fn disable_dma(&self);
+}
+
+pub struct Dma {
+    _dma: max32660_pac::DMA,
+}
+
+impl Dma {
+    pub fn new(dma: max32660_pac::DMA) -> Self {
+        Dma { _dma: dma }
+    }
+
+    /// Splits the controller into its four channels
+    pub fn split(self) -> (Channel, Channel, Channel, Channel) {
+        (
+            Channel { number: 0 },
+            Channel { number: 1 },
+            Channel { number: 2 },
+            Channel { number: 3 },
+        )
+    }
+}
+
+/// Source or destination of a transfer
+pub(crate) struct Endpoint {
+    address: u32,
+    increment: bool,
+}
+
+impl Endpoint {
+    /// A peripheral FIFO register, the address stays fixed
+    pub(crate) fn fifo(address: u32) -> Self {
+        Endpoint {
+            address,
+            increment: false,
+        }
+    }
+
+    /// A memory buffer, the address increments with each byte
+    pub(crate) fn memory(address: u32) -> Self {
+        Endpoint {
+            address,
+            increment: true,
+        }
+    }
+}
+
+pub struct Channel {
+    number: u8,
+}
+
+impl Channel {
+    fn regs(&self) -> &CH {
+        unsafe {
+            &*((max32660_pac::DMA::ptr() as *const u8).add(self.number as usize * CHANNEL_STRIDE)
+                as *const CH)
+        }
+    }
+
+    /// Configures and starts a byte wide transfer of `len` bytes. With `circular` set the
+    /// channel reloads its addresses and count when the count reaches zero and never stops.
+    ///
+    /// # Safety
+    ///
+    /// The `src` and `dst` addresses must stay valid for as long as the channel runs.
+    pub(crate) unsafe fn start(
+        &mut self,
+        request: Request,
+        src: Endpoint,
+        dst: Endpoint,
+        len: usize,
+        circular: bool,
+    ) {
+        self.stop();
+
+        let ch = self.regs();
+        ch.st.write(|w| w.ctz_st().set_bit().rld_st().set_bit().bus_err().set_bit().to_st().set_bit());
+
+        ch.src.write(|w| w.addr().bits(src.address));
+        ch.dst.write(|w| w.addr().bits(dst.address));
+        ch.cnt.write(|w| w.cnt().bits(len as u32));
+
+        if circular {
+            ch.src_rld.write(|w| w.src_rld().bits(src.address));
+            ch.dst_rld.write(|w| w.dst_rld().bits(dst.address));
+            ch.cnt_rld.write(|w| w.cnt_rld().bits(len as u32).rlden().set_bit());
+        }
+
+        // Make sure buffer writes are done before the DMA can see them
+        compiler_fence(Ordering::SeqCst);
+
+        ch.cfg.write(|w| {
+            w.reqsel()
+                .bits(request as u8)
+                .srcwd()
+                .byte()
+                .dstwd()
+                .byte()
+                .srcinc()
+                .bit(src.increment)
+                .dstinc()
+                .bit(dst.increment)
+                .brst()
+                .bits(0)
+                .rlden()
+                .bit(circular)
+                .chen()
+                .set_bit()
+        });
+    }
+
+    /// Disables the channel and waits until it has stopped
+    pub fn stop(&mut self) {
+        let ch = self.regs();
+        ch.cfg.modify(|_, w| w.chen().clear_bit());
+        while ch.st.read().ch_st().is_en() {}
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.regs().st.read().ch_st().is_en()
+    }
+
+    /// Bytes left before the count reaches zero
+    pub fn remaining(&self) -> usize {
+        self.regs().cnt.read().cnt().bits() as usize
+    }
+
+    pub fn bus_error(&self) -> bool {
+        self.regs().st.read().bus_err().is_occurred()
+    }
+
+    fn reload_pending(&self) -> bool {
+        self.regs().st.read().rld_st().is_occurred()
+    }
+
+    /// Returns true if the channel reloaded since the last call, clearing the flag
+    fn take_reload(&mut self) -> bool {
+        let ch = self.regs();
+        let reloaded = ch.st.read().rld_st().is_occurred();
+        if reloaded {
+            ch.st.write(|w| w.rld_st().set_bit());
+        }
+        reloaded
+    }
+}
+
+/// A one-shot transfer that owns the peripheral, channel and buffer until it completes
+pub struct Transfer<P: DmaPeripheral, B> {
+    peripheral: P,
+    channel: Channel,
+    buffer: B,
+}
+
+impl<P: DmaPeripheral, B> Transfer<P, B> {
+    pub(crate) fn new(peripheral: P, channel: Channel, buffer: B) -> Self {
+        Transfer {
+            peripheral,
+            channel,
+            buffer,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        !self.channel.is_busy()
+    }
+
+    /// Blocks until the transfer completes, then hands back the peripheral, channel and buffer
+    pub fn wait(self) -> (P, Channel, B) {
+        while !self.is_done() {}
+        compiler_fence(Ordering::SeqCst);
+        self.peripheral.disable_dma();
+
+        // Moves the fields out without running `drop`, which would stop the channel again
+        let this = ManuallyDrop::new(self);
+        unsafe {
+            (
+                ptr::read(&this.peripheral),
+                ptr::read(&this.channel),
+                ptr::read(&this.buffer),
+            )
+        }
+    }
+}
+
+/// Dropping an unfinished transfer stops it, so the DMA no longer writes the buffer
+impl<P: DmaPeripheral, B> Drop for Transfer<P, B> {
+    fn drop(&mut self) {
+        self.channel.stop();
+        self.peripheral.disable_dma();
+        compiler_fence(Ordering::SeqCst);
+    }
+}
+
+/// A transfer that keeps filling `buffer` as a ring, read out with `read`
+pub struct CircularTransfer<P: DmaPeripheral> {
+    peripheral: P,
+    channel: Channel,
+    buffer: &'static mut [u8],
+    read_index: usize,
+}
+
+impl<P: DmaPeripheral> CircularTransfer<P> {
+    pub(crate) fn new(peripheral: P, channel: Channel, buffer: &'static mut [u8]) -> Self {
+        CircularTransfer {
+            peripheral,
+            channel,
+            buffer,
+            read_index: 0,
+        }
+    }
+
+    /// Copies received bytes into `out`, returns how many were copied. Fails if the DMA
+    /// has wrapped past unread data, the read position then restarts at the newest data.
+    pub fn read(&mut self, out: &mut [u8]) -> Result<usize, DmaError> {
+        let len = self.buffer.len();
+
+        // The count has to be read after every reload it's paired with, so read it again
+        // if the channel reloaded in between
+        let mut wrapped = self.channel.take_reload();
+        let mut remaining = self.channel.remaining();
+        while self.channel.reload_pending() {
+            wrapped |= self.channel.take_reload();
+            remaining = self.channel.remaining();
+        }
+        let write_index = (len - remaining) % len;
+        compiler_fence(Ordering::SeqCst);
+
+        if wrapped && write_index >= self.read_index {
+            self.read_index = write_index;
+            return Err(DmaError::Overrun);
+        }
+
+        let available = if write_index >= self.read_index {
+            write_index - self.read_index
+        } else {
+            len - self.read_index + write_index
+        };
+
+        let count = available.min(out.len());
+        for byte in out.iter_mut().take(count) {
+            // The DMA keeps writing the buffer behind the compiler's back
+            *byte = unsafe { core::ptr::read_volatile(self.buffer.as_ptr().add(self.read_index)) };
+            self.read_index = (self.read_index + 1) % len;
+        }
+
+        Ok(count)
+    }
+
+    /// Stops the transfer and hands back the peripheral, channel and buffer
+    pub fn stop(self) -> (P, Channel, &'static mut [u8]) {
+        // `drop` stops the channel, the fields are then moved out without running it again
+        let mut this = ManuallyDrop::new(self);
+        this.halt();
+        unsafe {
+            (
+                ptr::read(&this.peripheral),
+                ptr::read(&this.channel),
+                ptr::read(&this.buffer),
+            )
+        }
+    }
+
+    fn halt(&mut self) {
+        self.channel.stop();
+        self.peripheral.disable_dma();
+        compiler_fence(Ordering::SeqCst);
+    }
+}
+
+impl<P: DmaPeripheral> Drop for CircularTransfer<P> {
+    fn drop(&mut self) {
+        self.halt();
+    }
+}
@@ -0,0 +1,202 @@
+//! DMX512 transmitter and receiver on top of `Uart0`/`Uart1`.
+//!
+//! Both ends expect the UART to be enabled and configured for 250 kbaud, 8 data bits
+//! and 2 stop bits:
+//!
+//! ```ignore
+//! uart.set_baud(pclk, dmx::BAUD)?;
+//! uart.set_char_size(CharSize::_8).set_stop_bit(StopBits::_2);
+//! uart.enable();
+//! ```
+
+use embedded_hal::blocking::delay::DelayUs;
+use embedded_hal::serial::{Read, Write};
+
+use crate::uart::{BreakControl, UartError};
+
+pub const BAUD: u32 = 250_000;
+
+/// Start code followed by 512 channel slots
+pub const SLOTS: usize = 513;
+
+/// Start code of a standard dimmer data packet
+pub const NULL_START_CODE: u8 = 0x00;
+
+/// Break and mark-after-break lengths used by default, the spec minimums are 92us and 12us
+const DEFAULT_BREAK_US: u32 = 176;
+const DEFAULT_MAB_US: u32 = 16;
+
+#[derive(Debug)]
+pub enum DmxError {
+    Uart(UartError),
+    /// More than 512 channel slots were passed to `send`
+    TooManySlots,
+}
+
+pub struct DmxTransmitter<S, D> {
+    uart: S,
+    delay: D,
+    break_us: u32,
+    mab_us: u32,
+}
+
+impl<S, D> DmxTransmitter<S, D>
+where
+    S: Write<u8, Error = UartError> + BreakControl,
+    D: DelayUs<u32>,
+{
+    pub fn new(uart: S, delay: D) -> Self {
+        DmxTransmitter {
+            uart,
+            delay,
+            break_us: DEFAULT_BREAK_US,
+            mab_us: DEFAULT_MAB_US,
+        }
+    }
+
+    /// Releases the UART and delay
+    pub fn free(self) -> (S, D) {
+        (self.uart, self.delay)
+    }
+
+    /// Sets the break and mark-after-break lengths in microseconds
+    pub fn set_timing(&mut self, break_us: u32, mab_us: u32) -> &mut Self {
+        self.break_us = break_us;
+        self.mab_us = mab_us;
+        self
+    }
+
+    /// Sends break, mark-after-break, the start code and up to 512 channel slots
+    pub fn send(&mut self, start_code: u8, slots: &[u8]) -> Result<(), DmxError> {
+        if slots.len() > SLOTS - 1 {
+            return Err(DmxError::TooManySlots);
+        }
+
+        // The previous packet has to be completely out before the line is pulled low
+        nb::block!(self.uart.flush()).map_err(DmxError::Uart)?;
+
+        self.uart.set_break(true);
+        self.delay.delay_us(self.break_us);
+        self.uart.set_break(false);
+        self.delay.delay_us(self.mab_us);
+
+        nb::block!(self.uart.write(start_code)).map_err(DmxError::Uart)?;
+        for slot in slots {
+            nb::block!(self.uart.write(*slot)).map_err(DmxError::Uart)?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends a full universe, `universe[0]` is the start code
+    pub fn send_universe(&mut self, universe: &[u8; SLOTS]) -> Result<(), DmxError> {
+        self.send(universe[0], &universe[1..])
+    }
+}
+
+pub struct DmxReceiver<S> {
+    uart: S,
+    receiving: [u8; SLOTS],
+    received: usize,
+    in_packet: bool,
+    latest: [u8; SLOTS],
+    latest_len: usize,
+}
+
+impl<S> DmxReceiver<S>
+where
+    S: Read<u8, Error = UartError>,
+{
+    pub fn new(uart: S) -> Self {
+        DmxReceiver {
+            uart,
+            receiving: [0; SLOTS],
+            received: 0,
+            in_packet: false,
+            latest: [0; SLOTS],
+            latest_len: 0,
+        }
+    }
+
+    /// Releases the UART
+    pub fn free(self) -> S {
+        self.uart
+    }
+
+    /// Copies the packet in progress to the latest universe buffer
+    fn commit(&mut self) -> bool {
+        let complete = self.in_packet && self.received > 0;
+        if complete {
+            self.latest[..self.received].copy_from_slice(&self.receiving[..self.received]);
+            self.latest_len = self.received;
+        }
+
+        self.in_packet = false;
+        self.received = 0;
+        complete
+    }
+
+    /// Drains the RX FIFO, call from the UART interrupt or often enough to keep the
+    /// FIFO from overflowing. Returns true if a new packet was completed.
+    ///
+    /// The FIFO has to be empty by the time the break arrives: the UART only flags that
+    /// a break is pending, so a 0x00 slot still queued ahead of it would be taken for
+    /// the break and the universe would shift by one slot. At 250 kbaud that leaves one
+    /// slot time (44 µs) after the last slot, so call it from the UART interrupt with
+    /// an RX FIFO threshold of 1 rather than from a slow loop.
+    ///
+    /// Data is only collected after a break, so the receiver re-synchronises on the
+    /// next break after any error or if started mid-packet.
+    pub fn poll(&mut self) -> bool {
+        let mut completed = false;
+
+        loop {
+            match self.uart.read() {
+                Ok(byte) => {
+                    if self.in_packet {
+                        self.receiving[self.received] = byte;
+                        self.received += 1;
+                        if self.received == SLOTS {
+                            completed |= self.commit();
+                        }
+                    }
+                }
+                Err(nb::Error::Other(UartError::Break)) => {
+                    // A break ends a short packet and starts the next one
+                    completed |= self.commit();
+                    self.in_packet = true;
+                }
+                Err(nb::Error::Other(_)) => {
+                    self.in_packet = false;
+                    self.received = 0;
+                }
+                Err(nb::Error::WouldBlock) => break,
+            }
+        }
+
+        completed
+    }
+
+    /// Start code of the latest packet
+    pub fn start_code(&self) -> Option<u8> {
+        if self.latest_len > 0 {
+            Some(self.latest[0])
+        } else {
+            None
+        }
+    }
+
+    /// Channel slots of the latest packet, `slots()[0]` is channel 1
+    pub fn slots(&self) -> &[u8] {
+        if self.latest_len > 0 {
+            &self.latest[1..self.latest_len]
+        } else {
+            &[]
+        }
+    }
+
+    /// The latest packet including its start code
+    pub fn universe(&self) -> &[u8] {
+        &self.latest[..self.latest_len]
+    }
+}
@@ -0,0 +1,193 @@
+//! Packet framing over a byte stream: COBS and SLIP encoders, streaming decoders that
+//! can be fed one byte at a time from `Uart0::read`, and `PacketUart`, which sends and
+//! receives whole CRC-checked packets.
+
+pub mod cobs;
+pub mod slip;
+
+use embedded_hal::serial::{Read, Write};
+
+use crate::crc;
+use crate::uart::UartError;
+
+/// Largest payload `PacketUart` sends or accepts
+pub const MAX_PAYLOAD: usize = 254;
+
+const CRC_LEN: usize = 2;
+
+/// Worst case encoded size of a packet: SLIP doubles every byte and adds two delimiters
+const MAX_ENCODED: usize = 2 * (MAX_PAYLOAD + CRC_LEN) + 2;
+
+#[derive(Debug)]
+pub enum FramingError {
+    /// The output or decoder buffer is too small for the frame
+    BufferTooSmall,
+    /// The received bytes are not a valid encoding, the frame was dropped
+    Malformed,
+    /// The packet's CRC doesn't match its contents
+    Crc,
+    Uart(UartError),
+}
+
+/// A frame encoding together with its streaming decoder
+pub trait Codec {
+    /// Encodes `data` into `out` as a complete frame including delimiters, returns the
+    /// encoded length
+    fn encode(&self, data: &[u8], out: &mut [u8]) -> Result<usize, FramingError>;
+
+    /// Feeds one received byte to the decoder, returns the length of the frame it completes
+    fn push(&mut self, byte: u8) -> Result<Option<usize>, FramingError>;
+
+    /// The decoder buffer, holding the last completed frame after `push` returned its length
+    fn buffer(&self) -> &[u8];
+}
+
+/// CRC-16/CCITT-FALSE (polynomial 0x1021, initial value 0xFFFF)
+pub fn crc16(data: &[u8]) -> u16 {
+    crc::CCITT_FALSE.checksum(data)
+}
+
+/// Sends and receives whole packets framed by `C`, each followed by a CRC-16
+pub struct PacketUart<S, C> {
+    uart: S,
+    codec: C,
+}
+
+impl<S, C> PacketUart<S, C>
+where
+    S: Read<u8, Error = UartError> + Write<u8, Error = UartError>,
+    C: Codec,
+{
+    pub fn new(uart: S, codec: C) -> Self {
+        PacketUart { uart, codec }
+    }
+
+    /// Releases the UART and codec
+    pub fn free(self) -> (S, C) {
+        (self.uart, self.codec)
+    }
+
+    /// Sends `payload` and its CRC as one frame, blocking until it is in the TX FIFO
+    pub fn send(&mut self, payload: &[u8]) -> Result<(), FramingError> {
+        if payload.len() > MAX_PAYLOAD {
+            return Err(FramingError::BufferTooSmall);
+        }
+
+        let mut packet = [0; MAX_PAYLOAD + CRC_LEN];
+        let len = payload.len();
+        packet[..len].copy_from_slice(payload);
+        packet[len..len + CRC_LEN].copy_from_slice(&crc16(payload).to_be_bytes());
+
+        let mut encoded = [0; MAX_ENCODED];
+        let encoded_len = self.codec.encode(&packet[..len + CRC_LEN], &mut encoded)?;
+
+        for byte in &encoded[..encoded_len] {
+            nb::block!(self.uart.write(*byte)).map_err(FramingError::Uart)?;
+        }
+        Ok(())
+    }
+
+    /// Reads everything available from the UART, returns the payload of the first
+    /// complete packet. Returns `WouldBlock` until a packet is complete.
+    pub fn receive(&mut self) -> nb::Result<&[u8], FramingError> {
+        let len = loop {
+            let byte = match self.uart.read() {
+                Ok(byte) => byte,
+                Err(nb::Error::WouldBlock) => return Err(nb::Error::WouldBlock),
+                Err(nb::Error::Other(e)) => return Err(nb::Error::Other(FramingError::Uart(e))),
+            };
+
+            if let Some(len) = self.codec.push(byte)? {
+                break len;
+            }
+        };
+
+        if len < CRC_LEN {
+            return Err(nb::Error::Other(FramingError::Malformed));
+        }
+
+        let frame = &self.codec.buffer()[..len];
+        let (payload, crc) = frame.split_at(len - CRC_LEN);
+        if crc16(payload).to_be_bytes() != crc {
+            return Err(nb::Error::Other(FramingError::Crc));
+        }
+
+        Ok(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// A UART whose transmitted bytes are received back
+    #[derive(Default)]
+    struct Loopback {
+        bytes: VecDeque<u8>,
+    }
+
+    impl Read<u8> for Loopback {
+        type Error = UartError;
+
+        fn read(&mut self) -> nb::Result<u8, UartError> {
+            self.bytes.pop_front().ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    impl Write<u8> for Loopback {
+        type Error = UartError;
+
+        fn write(&mut self, byte: u8) -> nb::Result<(), UartError> {
+            self.bytes.push_back(byte);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), UartError> {
+            Ok(())
+        }
+    }
+
+    fn round_trip<C: Codec>(codec: C) {
+        let mut packets = PacketUart::new(Loopback::default(), codec);
+        assert!(matches!(packets.receive(), Err(nb::Error::WouldBlock)));
+
+        let payload: [u8; MAX_PAYLOAD] = core::array::from_fn(|i| i as u8);
+        for len in [1, 2, 100, MAX_PAYLOAD] {
+            packets.send(&payload[..len]).unwrap();
+            assert_eq!(packets.receive().unwrap(), &payload[..len]);
+        }
+    }
+
+    #[test]
+    fn crc_known_answer() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn cobs_packets_round_trip() {
+        round_trip(cobs::Decoder::<{ MAX_PAYLOAD + CRC_LEN }>::new());
+    }
+
+    #[test]
+    fn slip_packets_round_trip() {
+        round_trip(slip::Decoder::<{ MAX_PAYLOAD + CRC_LEN }>::new());
+    }
+
+    #[test]
+    fn corrupted_packet_fails_crc() {
+        let mut packets = PacketUart::new(Loopback::default(), slip::Decoder::<16>::new());
+        packets.send(&[1, 2, 3]).unwrap();
+        let (mut uart, codec) = packets.free();
+        uart.bytes[2] ^= 0x01;
+
+        let mut packets = PacketUart::new(uart, codec);
+        assert!(matches!(packets.receive(), Err(nb::Error::Other(FramingError::Crc))));
+    }
+
+    #[test]
+    fn oversized_payload_is_rejected() {
+        let mut packets = PacketUart::new(Loopback::default(), cobs::Decoder::<16>::new());
+        assert!(matches!(packets.send(&[0; MAX_PAYLOAD + 1]), Err(FramingError::BufferTooSmall)));
+    }
+}
@@ -0,0 +1,293 @@
+//! Consistent Overhead Byte Stuffing, frames are delimited by a 0x00 byte.
+
+use super::{Codec, FramingError};
+
+const DELIMITER: u8 = 0x00;
+
+/// Largest block: a code byte followed by 254 non-zero bytes
+const MAX_CODE: u8 = 0xFF;
+
+/// Most bytes `len` bytes of data encode to, without the delimiter: one code byte per
+/// started block of 254
+pub fn max_encoded_len(len: usize) -> usize {
+    len + len.div_ceil(254).max(1)
+}
+
+/// Encodes `data` into `out` without the trailing delimiter, returns the encoded length
+pub fn encode(data: &[u8], out: &mut [u8]) -> Result<usize, FramingError> {
+    if out.len() < max_encoded_len(data.len()) {
+        return Err(FramingError::BufferTooSmall);
+    }
+
+    let mut code_index = 0;
+    let mut code = 1;
+    let mut out_index = 1;
+
+    for (index, byte) in data.iter().enumerate() {
+        if *byte == DELIMITER {
+            out[code_index] = code;
+            code_index = out_index;
+            out_index += 1;
+            code = 1;
+            continue;
+        }
+
+        out[out_index] = *byte;
+        out_index += 1;
+        code += 1;
+
+        // A full block that ends the data needs no empty block after it
+        if code == MAX_CODE && index + 1 < data.len() {
+            out[code_index] = code;
+            code_index = out_index;
+            out_index += 1;
+            code = 1;
+        }
+    }
+
+    out[code_index] = code;
+    Ok(out_index)
+}
+
+/// Decodes one frame, without its delimiter, into `out`. Returns the decoded length
+pub fn decode(data: &[u8], out: &mut [u8]) -> Result<usize, FramingError> {
+    let mut state = State::new();
+    for byte in data {
+        if state.step(*byte, out)?.is_some() {
+            return Err(FramingError::Malformed);
+        }
+    }
+    state.step(DELIMITER, out)?.ok_or(FramingError::Malformed)
+}
+
+/// Decoder state, kept apart from the output buffer so `decode` can share it
+struct State {
+    len: usize,
+    /// Bytes left in the current block, 0 when the next byte is a code
+    remaining: u8,
+    /// The current block ends with an implied zero
+    zero_pending: bool,
+    /// Dropping bytes until the next delimiter after an error
+    discarding: bool,
+}
+
+impl State {
+    fn new() -> Self {
+        State {
+            len: 0,
+            remaining: 0,
+            zero_pending: false,
+            discarding: false,
+        }
+    }
+
+    fn store(&mut self, byte: u8, out: &mut [u8]) -> Result<(), FramingError> {
+        if self.len >= out.len() {
+            return Err(FramingError::BufferTooSmall);
+        }
+        out[self.len] = byte;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Decodes one byte into `out`, returns the frame length on a delimiter
+    fn step(&mut self, byte: u8, out: &mut [u8]) -> Result<Option<usize>, FramingError> {
+        if byte == DELIMITER {
+            let complete = !self.discarding && self.remaining == 0;
+            let started = self.len > 0 || self.zero_pending;
+            let len = self.len;
+            *self = State::new();
+
+            return match (complete, started) {
+                (true, true) => Ok(Some(len)),
+                // Back-to-back delimiters are allowed and yield nothing
+                (true, false) => Ok(None),
+                (false, _) => Err(FramingError::Malformed),
+            };
+        }
+
+        if self.discarding {
+            return Ok(None);
+        }
+
+        let result = if self.remaining == 0 {
+            // A new block, the previous one ended with an implied zero unless it was full
+            let result = if self.zero_pending {
+                self.store(DELIMITER, out)
+            } else {
+                Ok(())
+            };
+            self.remaining = byte - 1;
+            self.zero_pending = byte != MAX_CODE;
+            result
+        } else {
+            self.remaining -= 1;
+            self.store(byte, out)
+        };
+
+        if result.is_err() {
+            self.discarding = true;
+        }
+        result.map(|_| None)
+    }
+}
+
+/// Streaming decoder collecting frames of up to `N` bytes
+pub struct Decoder<const N: usize> {
+    buffer: [u8; N],
+    state: State,
+}
+
+impl<const N: usize> Default for Decoder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Decoder<N> {
+    pub fn new() -> Self {
+        Decoder {
+            buffer: [0; N],
+            state: State::new(),
+        }
+    }
+
+    /// Feeds one received byte, returns the decoded frame once its delimiter arrives.
+    ///
+    /// After an error the rest of the frame is dropped and decoding restarts at the next
+    /// delimiter.
+    pub fn feed(&mut self, byte: u8) -> Result<Option<&[u8]>, FramingError> {
+        match self.push(byte)? {
+            Some(len) => Ok(Some(&self.buffer[..len])),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<const N: usize> Codec for Decoder<N> {
+    fn encode(&self, data: &[u8], out: &mut [u8]) -> Result<usize, FramingError> {
+        let len = encode(data, out)?;
+        if len >= out.len() {
+            return Err(FramingError::BufferTooSmall);
+        }
+        out[len] = DELIMITER;
+        Ok(len + 1)
+    }
+
+    fn push(&mut self, byte: u8) -> Result<Option<usize>, FramingError> {
+        self.state.step(byte, &mut self.buffer)
+    }
+
+    fn buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn encoded(data: &[u8]) -> Vec<u8> {
+        let mut out = [0; 600];
+        let len = encode(data, &mut out).unwrap();
+        out[..len].to_vec()
+    }
+
+    fn decoded(data: &[u8]) -> Vec<u8> {
+        let mut out = [0; 600];
+        let len = decode(data, &mut out).unwrap();
+        out[..len].to_vec()
+    }
+
+    #[test]
+    fn encodes_known_vectors() {
+        assert_eq!(encoded(&[0x00]), [0x01, 0x01]);
+        assert_eq!(encoded(&[0x00, 0x00]), [0x01, 0x01, 0x01]);
+        assert_eq!(encoded(&[0x11, 0x22, 0x00, 0x33]), [0x03, 0x11, 0x22, 0x02, 0x33]);
+        assert_eq!(encoded(&[0x11, 0x22, 0x33, 0x44]), [0x05, 0x11, 0x22, 0x33, 0x44]);
+        assert_eq!(encoded(&[0x11, 0x00, 0x00, 0x00]), [0x02, 0x11, 0x01, 0x01, 0x01]);
+    }
+
+    #[test]
+    fn full_block_at_end_has_no_trailing_block() {
+        let data: Vec<u8> = (0x01..=0xFE).collect();
+        let mut expected = vec![0xFF];
+        expected.extend(&data);
+        assert_eq!(encoded(&data), expected);
+        assert_eq!(max_encoded_len(data.len()), expected.len());
+
+        let data: Vec<u8> = (0x00..=0xFE).collect();
+        let mut expected = vec![0x01, 0xFF];
+        expected.extend(&data[1..]);
+        assert_eq!(encoded(&data), expected);
+
+        let data: Vec<u8> = (0x01..=0xFF).collect();
+        let mut expected = vec![0xFF];
+        expected.extend(&data[..254]);
+        expected.extend([0x02, 0xFF]);
+        assert_eq!(encoded(&data), expected);
+    }
+
+    #[test]
+    fn round_trips() {
+        for len in [0, 1, 2, 253, 254, 255, 256, 508, 509] {
+            for zero_every in [0, 1, 7, 254, 255] {
+                let data: Vec<u8> = (0..len)
+                    .map(|i| if zero_every != 0 && i % zero_every == 0 { 0 } else { (i % 255 + 1) as u8 })
+                    .collect();
+
+                let frame = encoded(&data);
+                assert!(frame.len() <= max_encoded_len(len), "len {}", len);
+                assert!(!frame.contains(&DELIMITER));
+                assert_eq!(decoded(&frame), data, "len {} zero every {}", len, zero_every);
+            }
+        }
+    }
+
+    #[test]
+    fn encode_fits_exact_buffer() {
+        let data = [0x42; 254];
+        let mut out = [0; 255];
+        assert_eq!(encode(&data, &mut out).unwrap(), 255);
+    }
+
+    #[test]
+    fn decoder_streams_frames() {
+        let mut decoder = Decoder::<16>::new();
+        let mut frame = [0; 16];
+        let len = decoder.encode(&[1, 0, 2], &mut frame).unwrap();
+
+        let mut frames = Vec::new();
+        for byte in frame[..len].iter().chain(&[DELIMITER]).chain(&frame[..len]) {
+            if let Some(data) = decoder.feed(*byte).unwrap() {
+                frames.push(data.to_vec());
+            }
+        }
+        assert_eq!(frames, [[1, 0, 2], [1, 0, 2]]);
+    }
+
+    #[test]
+    fn decoder_drops_truncated_block_and_recovers() {
+        let mut decoder = Decoder::<16>::new();
+        for byte in [0x05, 0x11, 0x22] {
+            assert!(decoder.feed(byte).unwrap().is_none());
+        }
+        assert!(matches!(decoder.feed(DELIMITER), Err(FramingError::Malformed)));
+
+        for byte in [0x02, 0x11] {
+            assert!(decoder.feed(byte).unwrap().is_none());
+        }
+        assert_eq!(decoder.feed(DELIMITER).unwrap(), Some(&[0x11][..]));
+    }
+
+    #[test]
+    fn decoder_rejects_oversized_frame() {
+        let mut decoder = Decoder::<2>::new();
+        for byte in [0x04, 1, 2] {
+            assert!(decoder.feed(byte).unwrap().is_none());
+        }
+        assert!(matches!(decoder.feed(3), Err(FramingError::BufferTooSmall)));
+        assert!(decoder.feed(DELIMITER).is_err());
+    }
+}
@@ -0,0 +1,217 @@
+//! Serial Line Internet Protocol (RFC 1055) framing, frames end with 0xC0.
+
+use super::{Codec, FramingError};
+
+const END: u8 = 0xC0;
+const ESC: u8 = 0xDB;
+const ESC_END: u8 = 0xDC;
+const ESC_ESC: u8 = 0xDD;
+
+/// Encodes `data` into `out`, starting and ending with END, returns the encoded length.
+/// The leading END flushes any noise the receiver collected since the last frame.
+pub fn encode(data: &[u8], out: &mut [u8]) -> Result<usize, FramingError> {
+    let mut len = 0;
+    let mut put = |byte: u8| {
+        let slot = out.get_mut(len).ok_or(FramingError::BufferTooSmall)?;
+        *slot = byte;
+        len += 1;
+        Ok(())
+    };
+
+    put(END)?;
+    for byte in data {
+        match *byte {
+            END => {
+                put(ESC)?;
+                put(ESC_END)?;
+            }
+            ESC => {
+                put(ESC)?;
+                put(ESC_ESC)?;
+            }
+            other => put(other)?,
+        }
+    }
+    put(END)?;
+
+    Ok(len)
+}
+
+/// Decodes one frame, without its END bytes, into `out`. Returns the decoded length
+pub fn decode(data: &[u8], out: &mut [u8]) -> Result<usize, FramingError> {
+    let mut state = State::new();
+    for byte in data {
+        if state.step(*byte, out)?.is_some() {
+            return Err(FramingError::Malformed);
+        }
+    }
+    Ok(state.step(END, out)?.unwrap_or(0))
+}
+
+/// Decoder state, kept apart from the output buffer so `decode` can share it
+struct State {
+    len: usize,
+    escaped: bool,
+    /// Dropping bytes until the next END after an error
+    discarding: bool,
+}
+
+impl State {
+    fn new() -> Self {
+        State {
+            len: 0,
+            escaped: false,
+            discarding: false,
+        }
+    }
+
+    /// Decodes one byte into `out`, returns the frame length on END
+    fn step(&mut self, byte: u8, out: &mut [u8]) -> Result<Option<usize>, FramingError> {
+        if byte == END {
+            let complete = !self.discarding && !self.escaped;
+            let len = self.len;
+            *self = State::new();
+
+            return match (complete, len) {
+                // Empty frames come from the leading END and are ignored
+                (true, 0) => Ok(None),
+                (true, len) => Ok(Some(len)),
+                (false, _) => Err(FramingError::Malformed),
+            };
+        }
+
+        if self.discarding {
+            return Ok(None);
+        }
+
+        let decoded = if self.escaped {
+            self.escaped = false;
+            match byte {
+                ESC_END => END,
+                ESC_ESC => ESC,
+                _ => {
+                    self.discarding = true;
+                    return Err(FramingError::Malformed);
+                }
+            }
+        } else if byte == ESC {
+            self.escaped = true;
+            return Ok(None);
+        } else {
+            byte
+        };
+
+        if self.len >= out.len() {
+            self.discarding = true;
+            return Err(FramingError::BufferTooSmall);
+        }
+        out[self.len] = decoded;
+        self.len += 1;
+        Ok(None)
+    }
+}
+
+/// Streaming decoder collecting frames of up to `N` bytes
+pub struct Decoder<const N: usize> {
+    buffer: [u8; N],
+    state: State,
+}
+
+impl<const N: usize> Default for Decoder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Decoder<N> {
+    pub fn new() -> Self {
+        Decoder {
+            buffer: [0; N],
+            state: State::new(),
+        }
+    }
+
+    /// Feeds one received byte, returns the decoded frame once its END arrives.
+    ///
+    /// After an error the rest of the frame is dropped and decoding restarts after the
+    /// next END.
+    pub fn feed(&mut self, byte: u8) -> Result<Option<&[u8]>, FramingError> {
+        match self.push(byte)? {
+            Some(len) => Ok(Some(&self.buffer[..len])),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<const N: usize> Codec for Decoder<N> {
+    fn encode(&self, data: &[u8], out: &mut [u8]) -> Result<usize, FramingError> {
+        encode(data, out)
+    }
+
+    fn push(&mut self, byte: u8) -> Result<Option<usize>, FramingError> {
+        self.state.step(byte, &mut self.buffer)
+    }
+
+    fn buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn encoded(data: &[u8]) -> Vec<u8> {
+        let mut out = [0; 600];
+        let len = encode(data, &mut out).unwrap();
+        out[..len].to_vec()
+    }
+
+    #[test]
+    fn escapes_end_and_esc() {
+        assert_eq!(encoded(&[END, ESC, 0x01]), [END, ESC, ESC_END, ESC, ESC_ESC, 0x01, END]);
+        assert_eq!(encoded(&[]), [END, END]);
+    }
+
+    #[test]
+    fn round_trips() {
+        let data: Vec<u8> = (0..=255).chain([END, END, ESC, ESC]).collect();
+        let frame = encoded(&data);
+
+        let mut out = [0; 600];
+        let len = decode(&frame[1..frame.len() - 1], &mut out).unwrap();
+        assert_eq!(&out[..len], &data[..]);
+    }
+
+    #[test]
+    fn decoder_streams_frames_and_skips_empty_ones() {
+        let mut decoder = Decoder::<8>::new();
+        let mut frames = Vec::new();
+        for byte in [END, 1, ESC, ESC_END, END, END, 2, END] {
+            if let Some(data) = decoder.feed(byte).unwrap() {
+                frames.push(data.to_vec());
+            }
+        }
+        assert_eq!(frames, [vec![1, END], vec![2]]);
+    }
+
+    #[test]
+    fn decoder_drops_bad_escape_and_recovers() {
+        let mut decoder = Decoder::<8>::new();
+        assert!(decoder.feed(1).unwrap().is_none());
+        assert!(decoder.feed(ESC).unwrap().is_none());
+        assert!(matches!(decoder.feed(0x00), Err(FramingError::Malformed)));
+        assert!(decoder.feed(2).unwrap().is_none());
+        assert!(matches!(decoder.feed(END), Err(FramingError::Malformed)));
+
+        assert!(decoder.feed(3).unwrap().is_none());
+        assert_eq!(decoder.feed(END).unwrap(), Some(&[3][..]));
+    }
+
+    #[test]
+    fn encode_reports_small_buffer() {
+        let mut out = [0; 3];
+        assert!(matches!(encode(&[END], &mut out), Err(FramingError::BufferTooSmall)));
+    }
+}
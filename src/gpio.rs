@@ -1,3 +1,5 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
 use max32660_pac;
 
 #[derive(Copy, Clone)]
@@ -5,7 +7,7 @@ pub enum Ports {
     _0,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq)]
 pub enum Pins {
     _0 = 0,
     _1,
@@ -90,11 +92,78 @@ fn pin_set(input: u32, pin: u8) -> u32 {
     input | pin_mask(pin)
 }
 
+/// Pins owned by a peripheral driver, bit n is pin n
+static CLAIMED: AtomicU32 = AtomicU32::new(0);
+
+/// Marks `pin` as owned by a driver, the `Gpio0::create_*` constructors then refuse it
+pub(crate) fn claim(pin: Pins) {
+    CLAIMED.fetch_or(pin_mask(pin as u8), Ordering::Relaxed);
+}
+
+/// Hands `pin` back from a driver
+pub(crate) fn release(pin: Pins) {
+    CLAIMED.fetch_and(!pin_mask(pin as u8), Ordering::Relaxed);
+}
+
+fn is_claimed(pin: Pins) -> bool {
+    CLAIMED.load(Ordering::Relaxed) & pin_mask(pin as u8) != 0
+}
+
+/// A pin known at compile time, lets drivers require specific pins in their signatures
+pub trait PinId {
+    const PIN: Pins;
+}
+
+macro_rules! typed_pins {
+    ($($type:ident, $field:ident, $pin:expr),+) => {
+        $(
+            pub struct $type {
+                _private: (),
+            }
+
+            impl PinId for $type {
+                const PIN: Pins = $pin;
+            }
+        )+
+
+        /// The typed pins of port 0, see `Gpio0::take_pins`
+        pub struct Parts {
+            $(pub $field: $type,)+
+        }
+
+        impl Parts {
+            fn new() -> Self {
+                Parts {
+                    $($field: $type { _private: () },)+
+                }
+            }
+        }
+    };
+}
+
+typed_pins!(
+    P0_0, p0_0, Pins::_0,
+    P0_1, p0_1, Pins::_1,
+    P0_2, p0_2, Pins::_2,
+    P0_3, p0_3, Pins::_3,
+    P0_4, p0_4, Pins::_4,
+    P0_5, p0_5, Pins::_5,
+    P0_6, p0_6, Pins::_6,
+    P0_7, p0_7, Pins::_7,
+    P0_8, p0_8, Pins::_8,
+    P0_9, p0_9, Pins::_9,
+    P0_10, p0_10, Pins::_10,
+    P0_11, p0_11, Pins::_11,
+    P0_12, p0_12, Pins::_12,
+    P0_13, p0_13, Pins::_13
+);
+
 #[macro_export]
 macro_rules! gpio {
     ($GPIOX:ty, $name:ident) => {
         pub struct $name {
             gpio: $GPIOX,
+            pins_taken: bool,
         }
 
         pub struct OutputPin {
@@ -105,12 +174,26 @@ macro_rules! gpio {
             pin: Pins,
         }
 
+        /// An output that only pulls low, emulating open drain for bit-banged buses
+        pub struct OpenDrainPin {
+            pin: Pins,
+        }
+
         impl $name {
             pub fn new(gpio: $GPIOX) -> Self {
-                $name { gpio: gpio }
+                $name {
+                    gpio: gpio,
+                    pins_taken: false,
+                }
             }
 
             fn create_pin(&self, pin: Pins, function: Function) {
+                assert!(!is_claimed(pin), "pin is owned by a peripheral driver");
+                Self::set_function(pin, function);
+            }
+
+            /// Switches `pin` to `function`, used by drivers that configure their own pins
+            pub(crate) fn set_function(pin: Pins, function: Function) {
                 let en_regs = match function {
                     Function::AF1 => [false, false, false],
                     Function::AF2 => [false, true, false],
@@ -120,8 +203,9 @@ macro_rules! gpio {
                 };
 
                 unsafe {
+                    let gpio = &*<$GPIOX>::ptr();
                     let mut reg = 0;
-                    self.gpio.en.modify(|r, w| {
+                    gpio.en.modify(|r, w| {
                         if en_regs[0] {
                             reg = pin_set(r.bits(), pin as u8);
                         } else {
@@ -129,7 +213,7 @@ macro_rules! gpio {
                         }
                         w.bits(reg)
                     });
-                    self.gpio.en1.modify(|r, w| {
+                    gpio.en1.modify(|r, w| {
                         if en_regs[1] {
                             reg = pin_set(r.bits(), pin as u8);
                         } else {
@@ -137,7 +221,7 @@ macro_rules! gpio {
                         }
                         w.bits(reg)
                     });
-                    self.gpio.en2.modify(|r, w| {
+                    gpio.en2.modify(|r, w| {
                         if en_regs[2] {
                             reg = pin_set(r.bits(), pin as u8);
                         } else {
@@ -148,6 +232,20 @@ macro_rules! gpio {
                 }
             }
 
+            /// Hands out the typed pins once, later calls return None
+            pub fn take_pins(&mut self) -> Option<Parts> {
+                if self.pins_taken {
+                    return None;
+                }
+                self.pins_taken = true;
+                Some(Parts::new())
+            }
+
+            /// Input levels of all pins, bit n is pin n
+            pub fn input_levels(&self) -> u32 {
+                self.gpio.in_.read().bits()
+            }
+
             pub fn create_alternate_function_pin(
                 &self,
                 pin: Pins,
@@ -160,6 +258,13 @@ macro_rules! gpio {
             /// Create's an input pin, see GPIO -> InputMode Configuration
             pub fn create_input_pin(&self, pin: Pins, resistor: Resistor) -> InputPin {
                 self.create_pin(pin, Function::Input);
+                Self::input_pin(pin, resistor)
+            }
+
+            /// Configures `pin` as an input, used by drivers that borrow their pins back
+            /// from a peripheral (e.g. `Uart0::enable_wake_on_rx`)
+            pub(crate) fn input_pin(pin: Pins, resistor: Resistor) -> InputPin {
+                Self::set_function(pin, Function::Input);
 
                 let mut pad = false;
                 let mut ps = false;
@@ -181,7 +286,8 @@ macro_rules! gpio {
 
                 // Note pad_cfg2 is not used for this part
                 unsafe {
-                    self.gpio.pad_cfg1.modify(|r, w| {
+                    let gpio = &*<$GPIOX>::ptr();
+                    gpio.pad_cfg1.modify(|r, w| {
                         let mut reg = r.bits();
                         if pad {
                             reg = pin_set(reg, pin as u8);
@@ -191,7 +297,7 @@ macro_rules! gpio {
                         w.bits(reg)
                     });
 
-                    self.gpio.ps.modify(|r, w| {
+                    gpio.ps.modify(|r, w| {
                         let mut reg = r.bits();
                         if ps {
                             reg = pin_set(reg, pin as u8);
@@ -207,6 +313,20 @@ macro_rules! gpio {
                 }
             }
 
+            /// Configures `pin` as an open-drain output, released so the pull-up takes it
+            /// high. Used by drivers that borrow their pins back (e.g. `I2c0::recover_bus`).
+            pub(crate) fn open_drain_pin(pin: Pins) -> OpenDrainPin {
+                Self::set_function(pin, Function::Output);
+
+                unsafe {
+                    let gpio = &*<$GPIOX>::ptr();
+                    gpio.out_clr.write(|w| w.bits(pin_set(0, pin as u8)));
+                    gpio.out_en.modify(|r, w| w.bits(pin_clear(r.bits(), pin as u8)));
+                }
+
+                OpenDrainPin { pin }
+            }
+
             pub fn create_output_pin(&self, pin: Pins, drive: DriveStrength) -> OutputPin {
                 self.create_pin(pin, Function::Output);
 
@@ -281,6 +401,56 @@ macro_rules! gpio {
             }
         }
 
+        impl embedded_hal::digital::v2::OutputPin for OutputPin {
+            type Error = core::convert::Infallible;
+
+            fn set_low(&mut self) -> Result<(), Self::Error> {
+                self.pin_low();
+                Ok(())
+            }
+
+            fn set_high(&mut self) -> Result<(), Self::Error> {
+                self.pin_high();
+                Ok(())
+            }
+        }
+
+        impl embedded_hal::digital::v2::InputPin for InputPin {
+            type Error = core::convert::Infallible;
+
+            fn is_high(&self) -> Result<bool, Self::Error> {
+                Ok(self.read())
+            }
+
+            fn is_low(&self) -> Result<bool, Self::Error> {
+                Ok(!self.read())
+            }
+        }
+
+        impl OpenDrainPin {
+            /// Pulls the line low
+            pub fn drive_low(&self) {
+                unsafe {
+                    (*<$GPIOX>::ptr())
+                        .out_en
+                        .modify(|r, w| w.bits(pin_set(r.bits(), self.pin as u8)));
+                }
+            }
+
+            /// Stops driving, the pull-up takes the line high unless another device holds it
+            pub fn release(&self) {
+                unsafe {
+                    (*<$GPIOX>::ptr())
+                        .out_en
+                        .modify(|r, w| w.bits(pin_clear(r.bits(), self.pin as u8)));
+                }
+            }
+
+            pub fn is_high(&self) -> bool {
+                unsafe { (*<$GPIOX>::ptr()).in_.read().bits() & pin_mask(self.pin as u8) != 0 }
+            }
+        }
+
         impl InputPin {
             pub fn read(&self) -> bool {
                 unsafe {
@@ -336,6 +506,54 @@ macro_rules! gpio {
                 }
             }
 
+            /// Lets this pin wake the device from sleep and deep sleep on the edge selected
+            /// with `enable_edge_interrupt`
+            pub fn enable_wakeup(&self) {
+                unsafe {
+                    (*<$GPIOX>::ptr()).wake_en.modify(|r, w| {
+                        w.bits(pin_set(r.bits(), self.pin as u8))
+                    });
+                    (*max32660_pac::PWRSEQ::ptr()).lpwk_en.modify(|r, w| {
+                        w.bits(pin_set(r.bits(), self.pin as u8))
+                    });
+                    (*max32660_pac::GCR::ptr()).pm.modify(|r, w| {
+                        w.bits(r.bits()).gpiowken().set_bit()
+                    });
+                }
+            }
+
+            /// Stops this pin waking the device, GPIO wakeup as a whole is turned off once no
+            /// pin is left enabled
+            pub fn disable_wakeup(&self) {
+                unsafe {
+                    (*<$GPIOX>::ptr()).wake_en.modify(|r, w| {
+                        w.bits(pin_clear(r.bits(), self.pin as u8))
+                    });
+
+                    let pwrseq = &*max32660_pac::PWRSEQ::ptr();
+                    pwrseq.lpwk_en.modify(|r, w| {
+                        w.bits(pin_clear(r.bits(), self.pin as u8))
+                    });
+                    if pwrseq.lpwk_en.read().bits() == 0 {
+                        (*max32660_pac::GCR::ptr()).pm.modify(|r, w| {
+                            w.bits(r.bits()).gpiowken().clear_bit()
+                        });
+                    }
+                }
+            }
+
+            /// Returns true if this pin woke the device, clearing the flag
+            pub fn take_wakeup(&self) -> bool {
+                unsafe {
+                    let pwrseq = &*max32660_pac::PWRSEQ::ptr();
+                    let woke = pwrseq.lp_wakefl.read().bits() & pin_mask(self.pin as u8) != 0;
+                    if woke {
+                        pwrseq.lp_wakefl.write(|w| w.bits(pin_mask(self.pin as u8)));
+                    }
+                    woke
+                }
+            }
+
             fn set_level_edge_interrupt(&self, mode: InterruptMode) {
                 unsafe {
                     (*<$GPIOX>::ptr()).int_mod.modify(|r, w| {
@@ -1,5 +1,15 @@
+use embedded_hal::blocking::delay::DelayUs;
+use embedded_hal::blocking::i2c::{Read, SevenBitAddress, TenBitAddress, Transactional, Write, WriteRead};
+pub use embedded_hal::blocking::i2c::Operation;
+use embedded_hal_1::i2c::{ErrorKind, NoAcknowledgeSource};
 use max32660_pac;
 
+use crate::gpio::{Function, Gpio0, OpenDrainPin, Pins};
+
+pub mod register_map;
+
+pub use register_map::{RegisterMapError, RegisterMapSlave};
+
 #[derive(Copy, Clone)]
 pub enum Interrupts0 {
     Done = 0x1,
@@ -26,10 +36,65 @@ pub enum Interrupts1 {
     TxUnderflow = 0x2,
 }
 
+#[derive(Copy, Clone, PartialEq)]
 pub enum Clock {
     Standard = 100000,
     Fast = 400000,
     FastPlus = 1000000,
+    /// Hs-mode, each transfer starts with the master code sent at Fast-mode speed
+    HighSpeed = 3400000,
+}
+
+/// Minimum SCL low and high times and maximum rise time of a speed mode, in ns
+struct Timing {
+    low_ns: u32,
+    high_ns: u32,
+    rise_ns: u32,
+}
+
+impl Clock {
+    /// From the I2C specification (UM10204), Hs-mode for a 100 pF bus
+    fn timing(&self) -> Timing {
+        match self {
+            Clock::Standard => Timing { low_ns: 4700, high_ns: 4000, rise_ns: 1000 },
+            Clock::Fast => Timing { low_ns: 1300, high_ns: 600, rise_ns: 300 },
+            Clock::FastPlus => Timing { low_ns: 500, high_ns: 260, rise_ns: 120 },
+            Clock::HighSpeed => Timing { low_ns: 160, high_ns: 60, rise_ns: 40 },
+        }
+    }
+}
+
+/// PCLK cycles CLK_LO and CLK_HI can count
+const MAX_CLK_CYCLES: u32 = 512;
+
+/// PCLK cycles HS_CLK_LO and HS_CLK_HI can count
+const MAX_HS_CLK_CYCLES: u32 = 256;
+
+fn ns_to_cycles(ns: u32, peripheral_clock: u32) -> u32 {
+    (ns as u64 * peripheral_clock as u64).div_ceil(1_000_000_000) as u32
+}
+
+/// Splits the SCL period of `speed` into low and high PCLK cycles. The high count only
+/// starts once SCL is seen high, so the rise time is taken off the period, and what's
+/// left over the spec minimums is shared evenly.
+fn scl_cycles(peripheral_clock: u32, speed: Clock, max_cycles: u32) -> Result<(u32, u32), Error> {
+    let timing = speed.timing();
+    let period = peripheral_clock.div_ceil(speed as u32);
+    let budget = period.saturating_sub(ns_to_cycles(timing.rise_ns, peripheral_clock));
+
+    let min_low = ns_to_cycles(timing.low_ns, peripheral_clock).max(1);
+    let min_high = ns_to_cycles(timing.high_ns, peripheral_clock).max(1);
+    if min_low + min_high > budget {
+        return Err(Error::SpeedUnachievable);
+    }
+
+    let low = min_low + (budget - min_low - min_high) / 2;
+    let high = budget - low;
+    if low > max_cycles || high > max_cycles {
+        return Err(Error::SpeedUnachievable);
+    }
+
+    Ok((low, high))
 }
 
 pub enum IntThresh {
@@ -44,40 +109,199 @@ pub enum IntThresh {
     _8,
 }
 
-#[derive(Debug)]
-pub enum I2CWriteError {
-    NACK,
+/// Why a transfer failed, decoded from the error flags in INT_FL0 and INT_FL1
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Error {
+    /// Another master won arbitration
+    ArbitrationLost,
+    /// SCL was held low longer than the timeout, 1 ms unless changed with `set_timeout`
+    Timeout,
+    /// The address wasn't acknowledged, the device may be missing
+    AddressNack,
+    /// A data byte wasn't acknowledged
+    DataNack,
+    /// As a slave, the master addressed us while the FIFO wasn't ready
+    DoNotRespond,
+    /// A START condition was detected out of sequence
+    Start,
+    /// A STOP condition was detected out of sequence
+    Stop,
+    /// A byte was received while the RX FIFO was full
+    RxOverflow,
+    /// A byte had to be sent while the TX FIFO was empty
+    TxUnderflow,
+    /// More bytes were requested than RX_CNT can count in one transfer
+    BufferTooLong,
+    /// An interrupt-driven transfer is still in progress
+    Busy,
+    /// SCL or SDA was still held low during bus recovery
+    BusStuck,
+    /// The bus didn't go idle after STOP, e.g. because a slave holds SDA low
+    StopTimeout,
+    /// The bus speed can't be generated from the peripheral clock within the spec's timing
+    SpeedUnachievable,
+    /// The timeout is longer than the timeout register can count
+    TimeoutTooLong,
 }
 
-#[derive(Debug)]
-pub enum I2CReadError {
-    NACK,
-    Timeout,
-    UnspecifiedError,
+impl embedded_hal_1::i2c::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::ArbitrationLost => ErrorKind::ArbitrationLoss,
+            Error::AddressNack => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address),
+            Error::DataNack => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data),
+            Error::Start | Error::Stop | Error::BusStuck | Error::StopTimeout => ErrorKind::Bus,
+            Error::RxOverflow | Error::TxUnderflow => ErrorKind::Overrun,
+            Error::Timeout
+            | Error::DoNotRespond
+            | Error::BufferTooLong
+            | Error::Busy
+            | Error::SpeedUnachievable
+            | Error::TimeoutTooLong => ErrorKind::Other,
+        }
+    }
 }
 
 const READ_BIT: u8 = 0x1;
 const WRITE_BIT: u8 = 0x0;
 const ADDRESS_10BIT: u8 = 0xF0;
 
+/// PCLK cycles the timeout register can count
+const MAX_TIMEOUT_CYCLES: u64 = 0xFFFF;
+
+/// SCL low time `enable` sets as timeout, so a stuck bus can't hang a transfer. Shortened
+/// to what the register can count at fast clocks.
+const DEFAULT_TIMEOUT_US: u32 = 1_000;
+
+fn timeout_cycles(peripheral_clock: u32, timeout_us: u32) -> u64 {
+    (timeout_us as u64 * peripheral_clock as u64).div_ceil(1_000_000)
+}
+
+/// How long `master_stop` and `probe` wait for the bus
+const STOP_TIMEOUT_US: u32 = 1_000;
+
+/// Half an SCL period of the recovery clock, 100 kHz
+const RECOVERY_HALF_PERIOD_US: u32 = 5;
+
+/// Clock pulses that get any slave through the rest of a byte and its ACK
+const RECOVERY_PULSES: usize = 9;
+
+/// How long a slave may stretch a recovery clock pulse
+const RECOVERY_STRETCH_US: u32 = 1_000;
+
+/// Most bytes one read can receive, RX_CNT = 0 counts 256
+const MAX_READ_LEN: usize = 256;
+
+/// Target address of a transfer
+#[derive(Copy, Clone)]
+enum Address {
+    Seven(u8),
+    Ten(u16),
+}
+
+/// Interrupts serviced by `on_interrupt` in every phase of a transfer
+const IRQ_ERRORS: u32 = Interrupts0::Done as u32
+    | Interrupts0::ArbEr as u32
+    | Interrupts0::ToEr as u32
+    | Interrupts0::AddrEr as u32
+    | Interrupts0::DataEr as u32
+    | Interrupts0::DoNotRespondEr as u32
+    | Interrupts0::StartEr as u32
+    | Interrupts0::StopEr as u32;
+
+/// TX FIFO level at which more data is queued during an interrupt-driven write
+const IRQ_TX_THRESH: u8 = 2;
+
+/// Most bytes waited for before an interrupt-driven read empties the RX FIFO
+const IRQ_RX_THRESH: usize = 4;
+
+/// Called from `on_interrupt` when an interrupt-driven transfer ends
+pub type CompletionCallback = fn(Result<(), Error>);
+
+/// Step of an interrupt-driven transfer
+#[derive(Copy, Clone, PartialEq)]
+enum Phase {
+    Writing,
+    Reading,
+    Stopping,
+}
+
+/// An interrupt-driven transfer in progress
+struct IrqTransfer {
+    address: Address,
+    bytes: &'static [u8],
+    written: usize,
+    buffer: &'static mut [u8],
+    read: usize,
+    phase: Phase,
+}
+
+/// Own address when acting as a slave
+#[derive(Copy, Clone)]
+pub enum SlaveAddress {
+    Seven(u8),
+    Ten(u16),
+}
+
+/// What the master did, returned by `slave_poll`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SlaveEvent {
+    /// The master addressed us to write, `general_call` if it used the general call address
+    WriteRequest { general_call: bool },
+    /// The master addressed us to read, answer the following `ByteRequested` events
+    ReadRequest,
+    /// A byte written by the master
+    ByteReceived(u8),
+    /// The master is waiting for data, SCL is stretched until `slave_respond` queues some
+    ByteRequested,
+    /// The transfer ended with STOP
+    Stop,
+}
+
+/// Slave side of a bus, implemented by `I2c0`/`I2c1` in slave mode
+pub trait Slave {
+    /// Returns the next bus event, see `I2c0::slave_poll`
+    fn poll(&mut self) -> Option<SlaveEvent>;
+
+    /// Queues `data` for the master to read, returns how many bytes were queued
+    fn respond(&mut self, data: &[u8]) -> usize;
+
+    /// Returns how many queued bytes were dropped unread since the last call, see
+    /// `I2c0::slave_take_unsent`
+    fn take_unsent(&mut self) -> usize;
+}
+
+/// Direction of the slave transfer in progress
+#[derive(Copy, Clone, PartialEq)]
+enum SlaveState {
+    Idle,
+    Receiving,
+    Transmitting,
+}
+
 macro_rules! i2c {
-    ($type:ty, $name:ident, $buffer_size:expr) => {
+    ($type:ty, $name:ident, $scl:expr, $sda:expr) => {
         pub struct $name {
             i2c: $type,
             busy: bool,
-            bytes_read: usize,
-            buffer: [u8; $buffer_size],
+            slave: SlaveState,
+            transfer: Option<IrqTransfer>,
+            completed: Option<(Result<(), Error>, &'static mut [u8])>,
+            callback: Option<CompletionCallback>,
+            /// Bytes queued by `slave_respond` that the master never read
+            unsent: usize,
         }
         
         impl $name {
             pub fn new(i2c: $type) -> Self {
-                assert!($buffer_size < 255, "BUFFER_SIZE should be 0 to 255");
-                
                 $name {
                     i2c: i2c,
                     busy: false,
-                    bytes_read: 0,
-                    buffer: [0; $buffer_size],
+                    slave: SlaveState::Idle,
+                    transfer: None,
+                    completed: None,
+                    callback: None,
+                    unsent: 0,
                 }
             } 
         
@@ -93,23 +317,48 @@ macro_rules! i2c {
                 !self.busy
             }
         
-            pub fn buffer(&self) -> &[u8; $buffer_size] {
-                &self.buffer
-            } 
-        
-            pub fn enable(&self, peripheral_clock: u32, i2c_speed: Clock) -> &Self{
-                let tclock: u32 = (peripheral_clock) / (i2c_speed as u32);
-        
-                let t_hi = ((tclock >> 1) - 1) as u8;
-                let t_low = ((tclock >> 1) - 1) as u8;
-        
+            /// Enables the I2C as master at `i2c_speed`, with SCL low and high times that
+            /// meet the spec at that speed and a 1 ms timeout (see `set_timeout`). Fails if
+            /// `peripheral_clock` is too slow or too fast for the clock dividers.
+            pub fn enable(&self, peripheral_clock: u32, i2c_speed: Clock) -> Result<&Self, Error> {
+                let high_speed = i2c_speed == Clock::HighSpeed;
+
+                // In Hs-mode CLK_LO/CLK_HI time the master code preamble
+                let base = if high_speed { Clock::Fast } else { i2c_speed };
+                let (low, high) = scl_cycles(peripheral_clock, base, MAX_CLK_CYCLES)?;
+                let hs = if high_speed {
+                    Some(scl_cycles(peripheral_clock, Clock::HighSpeed, MAX_HS_CLK_CYCLES)?)
+                } else {
+                    None
+                };
+
+                let timeout = timeout_cycles(peripheral_clock, DEFAULT_TIMEOUT_US).min(MAX_TIMEOUT_CYCLES);
+
                 unsafe {
-                    self.i2c.clk_hi.write(|w| w.bits(t_hi as u32));
-                    self.i2c.clk_lo.write(|w| w.bits(t_low as u32));
-        
+                    self.i2c.timeout.write(|w| w.to().bits(timeout as u16));
+                    self.i2c.clk_lo.write(|w| w.clk_lo().bits((low - 1) as u16));
+                    self.i2c.clk_hi.write(|w| w.ckh().bits((high - 1) as u16));
+
+                    if let Some((hs_low, hs_high)) = hs {
+                        self.i2c.hs_clk.write(|w| {
+                            w.hs_clk_lo().bits((hs_low - 1) as u8).hs_clk_hi().bits((hs_high - 1) as u8)
+                        });
+                    }
+
                     self.i2c.ctrl.modify(|r, w| {
-                        w.bits(r.bits()).i2c_en().en().mst().master_mode()
-                    }); 
+                        w.bits(r.bits()).hs_mode().bit(high_speed).i2c_en().en().mst().master_mode()
+                    });
+                }
+                Ok(self)
+            }
+
+            /// Sets the low 3 bits of the Hs-mode master code 0000_1xxx, every master on
+            /// the bus needs its own
+            pub fn set_master_code(&self, code: u8) -> &Self {
+                unsafe {
+                    self.i2c.master_ctrl.modify(|r, w| {
+                        w.bits(r.bits()).master_code().bits(code & 0x7)
+                    });
                 }
                 self
             }
@@ -123,6 +372,21 @@ macro_rules! i2c {
                 self
             }
         
+            /// Sets how long SCL may be held low, e.g. by a slave stretching the clock,
+            /// before the transfer fails with `Error::Timeout`. 0 disables the timeout,
+            /// transfers then wait for a stuck bus forever.
+            pub fn set_timeout(&self, peripheral_clock: u32, timeout_us: u32) -> Result<&Self, Error> {
+                let cycles = timeout_cycles(peripheral_clock, timeout_us);
+                if cycles > MAX_TIMEOUT_CYCLES {
+                    return Err(Error::TimeoutTooLong);
+                }
+
+                unsafe {
+                    self.i2c.timeout.write(|w| w.to().bits(cycles as u16));
+                }
+                Ok(self)
+            }
+
             pub fn rx_int_threshold(&self, threshold: IntThresh) -> &Self {
                 unsafe {
                     self.i2c.rx_ctrl0.modify(|r, w| {
@@ -176,21 +440,157 @@ macro_rules! i2c {
                 self.i2c.rx_ctrl1.read().rx_fifo().bits() as u8
             }
         
-            pub fn master_stop(&mut self) {
+            /// Sends STOP and waits up to `STOP_TIMEOUT_US` for the bus to go idle. Fails
+            /// with `Error::StopTimeout` if it doesn't or the hardware flags an error, e.g.
+            /// because a slave holds SDA low; free the bus with `recover_bus` then.
+            pub fn master_stop<D: DelayUs<u32> + ?Sized>(&mut self, delay: &mut D) -> Result<(), Error> {
+                self.abort();
+                // Only errors raised while stopping count
+                self.clear_flags();
+
+                let mut waited_us = 0;
+                while !self.i2c.status.read().status().is_idle() {
+                    if self.fault().is_some() || waited_us >= STOP_TIMEOUT_US {
+                        return Err(Error::StopTimeout);
+                    }
+                    delay.delay_us(1);
+                    waited_us += 1;
+                }
+                Ok(())
+            }
+
+            /// Sends STOP and releases the TX FIFO locked by an error without waiting for the
+            /// bus, so it can be called from the interrupt handler
+            fn abort(&mut self) {
                 self.busy = false;
-                self.i2c.master_ctrl.write(|w| {
-                    w.stop().set_bit()
-                });
-        
+                // Keeps the master code and SL_EX_ADDR
+                self.send_stop();
+
                 unsafe {
                     self.i2c.int_fl0.modify(|r, w| {
                         w.bits(r.bits()).tx_lock_out().set_bit()
                     });
                 }
-        
-                while !self.i2c.status.read().status().is_idle() {}
+            }
+
+            /// Frees a bus held by a slave that was reset mid-transfer. SCL and SDA are
+            /// switched to GPIO, SCL is pulsed up to nine times until the slave releases
+            /// SDA, then a STOP is generated by hand and the pins are handed back to the
+            /// I2C. Fails with `Error::BusStuck` if SCL or SDA stays low.
+            pub fn recover_bus<D: DelayUs<u32>>(&mut self, delay: &mut D) -> Result<(), Error> {
+                unsafe {
+                    self.i2c.ctrl.modify(|r, w| {
+                        w.bits(r.bits()).i2c_en().dis()
+                    });
+                }
+
+                let scl = Gpio0::open_drain_pin($scl);
+                let sda = Gpio0::open_drain_pin($sda);
+
+                let mut result = Ok(());
+                for _ in 0..RECOVERY_PULSES {
+                    if sda.is_high() {
+                        break;
+                    }
+                    scl.drive_low();
+                    delay.delay_us(RECOVERY_HALF_PERIOD_US);
+                    result = Self::release_scl(&scl, delay);
+                    if result.is_err() {
+                        break;
+                    }
+                    delay.delay_us(RECOVERY_HALF_PERIOD_US);
+                }
+
+                if result.is_ok() {
+                    // STOP: SDA rises while SCL is high
+                    scl.drive_low();
+                    delay.delay_us(RECOVERY_HALF_PERIOD_US);
+                    sda.drive_low();
+                    delay.delay_us(RECOVERY_HALF_PERIOD_US);
+                    result = Self::release_scl(&scl, delay);
+                    delay.delay_us(RECOVERY_HALF_PERIOD_US);
+                    sda.release();
+                    delay.delay_us(RECOVERY_HALF_PERIOD_US);
+
+                    if result.is_ok() && !sda.is_high() {
+                        result = Err(Error::BusStuck);
+                    }
+                }
+
+                scl.release();
+                sda.release();
+                Gpio0::set_function($scl, Function::AF1);
+                Gpio0::set_function($sda, Function::AF1);
+
+                unsafe {
+                    self.i2c.rx_ctrl0.modify(|r, w| {
+                        w.bits(r.bits()).rx_flush().set_bit()
+                    });
+                    self.i2c.tx_ctrl0.modify(|r, w| {
+                        w.bits(r.bits()).tx_flush().set_bit()
+                    });
+                    self.i2c.ctrl.modify(|r, w| {
+                        w.bits(r.bits()).i2c_en().en()
+                    });
+                }
+                self.clear_flags();
+                self.busy = false;
+
+                result
+            }
+
+            /// Releases SCL and waits up to `RECOVERY_STRETCH_US` for it to go high, a slave
+            /// may stretch the clock
+            fn release_scl<D: DelayUs<u32>>(scl: &OpenDrainPin, delay: &mut D) -> Result<(), Error> {
+                scl.release();
+
+                let mut waited_us = 0;
+                while !scl.is_high() {
+                    if waited_us >= RECOVERY_STRETCH_US {
+                        return Err(Error::BusStuck);
+                    }
+                    delay.delay_us(1);
+                    waited_us += 1;
+                }
+                Ok(())
             }
         
+            /// Addresses the 7-bit `address` for writing and stops, returns true if it was
+            /// acknowledged. Waits at most `STOP_TIMEOUT_US` for each of the address and STOP.
+            /// Fails with `Error::Busy` during an interrupt-driven transfer.
+            pub fn probe<D: DelayUs<u32> + ?Sized>(&mut self, address: u8, delay: &mut D) -> Result<bool, Error> {
+                if self.transfer.is_some() {
+                    return Err(Error::Busy);
+                }
+                self.busy = true;
+                self.set_address_width(false);
+
+                unsafe {
+                    // Clear all flags, including the TX lock out
+                    self.i2c.int_fl0.write(|w| w.bits(0xFFFF));
+
+                    self.i2c.fifo.write(|w| w.data().bits(address << 1 | WRITE_BIT));
+                    self.i2c.master_ctrl.modify(|r, w| {
+                        w.bits(r.bits()).start().set_bit()
+                    });
+                }
+
+                let mut waited_us = 0;
+                let acked = loop {
+                    if self.i2c.int_fl0.read().addr_ack().bit() {
+                        break true;
+                    }
+                    if self.fault().is_some() || waited_us >= STOP_TIMEOUT_US {
+                        break false;
+                    }
+                    delay.delay_us(1);
+                    waited_us += 1;
+                };
+
+                // A bus that doesn't go idle counts as nothing answering
+                Ok(self.master_stop(delay).is_ok() && acked)
+            }
+
             fn _10bit_address_to_bytes(address: u16, read: bool) -> [u8; 2] {
                 let mut retval = [0; 2];
         
@@ -202,7 +602,7 @@ macro_rules! i2c {
         
                 let upper = ((address & 0x300) >> 7) & !0x1;
                 retval[0] = (upper as u8) | ADDRESS_10BIT | (if read { READ_BIT } else { WRITE_BIT });
-                retval[1] = (address & 0x7F) as u8;
+                retval[1] = (address & 0xFF) as u8;
         
                 retval
             }
@@ -217,147 +617,675 @@ macro_rules! i2c {
                 *data_offset
             }
         
-            pub fn master_write(&mut self, address: u16, data: &[u8], _10_bit: bool)-> Result<(), I2CWriteError> {
+            /// Writes `data` to `address`, ending with STOP
+            pub fn master_write(&mut self, address: u16, data: &[u8], _10_bit: bool)-> Result<(), Error> {
                 // See Max32660 User Guide Section 14.4.6.2 I2C Master Tx Operation
+
+                let address = if _10_bit {
+                    Address::Ten(address)
+                } else {
+                    Address::Seven(address as u8)
+                };
+
+                self.execute(address, &mut [Operation::Write(data)])
+            }
+
+            /// Reads `buffer.len()` bytes, up to the 256 RX_CNT can count, from `address`.
+            /// Returns the number of bytes read.
+            pub fn master_read(&mut self, address: u16, buffer: &mut [u8], _10_bit: bool) -> Result<usize, Error> {
+                // See Max32660 User Guide Section 14.4.6.1 I2C Master Receiver Operation
+
+                let address = if _10_bit {
+                    Address::Ten(address)
+                } else {
+                    Address::Seven(address as u8)
+                };
+
+                let len = buffer.len();
+                self.execute(address, &mut [Operation::Read(buffer)])?;
+
+                Ok(len)
+            }
         
-                self.busy = true;
-        
-                let mut data_offset = 0;
-        
+            /// Switches to slave mode, answering to `address` and, if `general_call` is set,
+            /// to the general call address 0. Bus events are then handled with `slave_poll`.
+            pub fn slave_mode(&mut self, address: SlaveAddress, general_call: bool) -> &Self {
+                self.slave = SlaveState::Idle;
+                self.unsent = 0;
+
                 unsafe {
-                    if _10_bit {
-                        let address_as_array = Self::_10bit_address_to_bytes(address, false);
-        
-                        self.i2c.master_ctrl.modify(|r, w| {
-                            w.bits(r.bits()).sl_ex_addr().set_bit()
-                        });
-        
-                      
-                        for byte in address_as_array {
-                            self.i2c.fifo.write(|w| w.data().bits(byte));
+                    self.i2c.ctrl.modify(|r, w| {
+                        w.bits(r.bits()).i2c_en().dis()
+                    });
+
+                    self.i2c.slave_addr.write(|w| match address {
+                        SlaveAddress::Seven(address) => {
+                            w.slave_addr().bits(address as u16 & 0x7F).ex_addr()._7_bits_address()
                         }
-                    }else{
-                        let address_u8 = (address as u8) << 1;
-                        self.i2c.fifo.write(|w| w.data().bits(address_u8));
+                        SlaveAddress::Ten(address) => {
+                            w.slave_addr().bits(address & 0x3FF).ex_addr()._10_bits_address()
+                        }
+                    });
+
+                    self.i2c.rx_ctrl0.modify(|r, w| {
+                        w.bits(r.bits()).rx_flush().set_bit()
+                    });
+                    self.i2c.tx_ctrl0.modify(|r, w| {
+                        w.bits(r.bits()).tx_flush().set_bit().tx_preload().clear_bit()
+                    });
+
+                    self.i2c.ctrl.modify(|r, w| {
+                        w.bits(r.bits())
+                            .mst().slave_mode()
+                            .gen_call_addr().bit(general_call)
+                            .scl_clk_strech_dis().clear_bit()
+                            .i2c_en().en()
+                    });
+                }
+                self.clear_flags();
+                self
+            }
+
+            /// Returns the next bus event in slave mode, or None if nothing happened. Call
+            /// it from a loop, or from the interrupt handler with `AddrMatch`, `GenCtrlAddr`,
+            /// `RxThresh`, `TxThresh` and `Stop` enabled until it returns None.
+            pub fn slave_poll(&mut self) -> Option<SlaveEvent> {
+                let flags = self.i2c.int_fl0.read();
+                let addressed = flags.addr_match().bit() || flags.gen_call_addr().bit();
+
+                // Bytes of the current write come before a repeated START or STOP
+                if self.slave != SlaveState::Transmitting && !self.i2c.status.read().rx_empty().bit() {
+                    return Some(SlaveEvent::ByteReceived(self.i2c.fifo.read().data().bits()));
+                }
+
+                if addressed {
+                    let general_call = flags.gen_call_addr().bit();
+                    self.i2c.int_fl0.write(|w| w.addr_match().set_bit().gen_call_addr().set_bit());
+
+                    if self.i2c.ctrl.read().read().is_read() {
+                        self.slave = SlaveState::Transmitting;
+                        // Drop unsent bytes and release the FIFO locked by the match
+                        self.flush_unsent();
+                        self.i2c.int_fl0.write(|w| w.tx_lock_out().set_bit());
+                        return Some(SlaveEvent::ReadRequest);
                     }
-        
-                    // 1. Write initial data to buffer, R/W bit is 0
-                    data_offset = self.buffer_tx_data(data, &mut data_offset);
-                    
-                    // 2. Send start
+
+                    self.slave = SlaveState::Receiving;
+                    return Some(SlaveEvent::WriteRequest { general_call });
+                }
+
+                if flags.stop().bit() {
+                    self.slave = SlaveState::Idle;
+                    self.i2c.int_fl0.write(|w| w.stop().set_bit().done().set_bit().tx_lock_out().set_bit());
+                    self.flush_unsent();
+                    return Some(SlaveEvent::Stop);
+                }
+
+                if self.slave == SlaveState::Transmitting {
+                    // The master NACKed the last byte, it wants no more data
+                    if flags.done().bit() {
+                        self.slave = SlaveState::Idle;
+                        return None;
+                    }
+                    if self.i2c.status.read().tx_empty().bit() {
+                        return Some(SlaveEvent::ByteRequested);
+                    }
+                }
+
+                None
+            }
+
+            /// Queues `data` for the master to read, returns how many bytes fit in the FIFO
+            pub fn slave_respond(&mut self, data: &[u8]) -> usize {
+                let mut offset = 0;
+                self.buffer_tx_data(data, &mut offset)
+            }
+
+            /// Returns how many bytes queued with `slave_respond` were dropped unread since
+            /// the last call. The FIFO takes bytes before the master clocks them out, so a
+            /// read the master ends with NACK leaves some behind, flushed at STOP or the
+            /// next read.
+            pub fn slave_take_unsent(&mut self) -> usize {
+                core::mem::take(&mut self.unsent)
+            }
+
+            /// Flushes the TX FIFO, counting the bytes in it as unsent
+            fn flush_unsent(&mut self) {
+                self.unsent += self.tx_fifo_len() as usize;
+                unsafe {
+                    self.i2c.tx_ctrl0.modify(|r, w| {
+                        w.bits(r.bits()).tx_flush().set_bit()
+                    });
+                }
+            }
+
+
+            fn clear_flags(&self) {
+                unsafe {
+                    // Also clears the TX lock out left by an earlier error
+                    self.i2c.int_fl0.write(|w| w.bits(0xFFFF));
+                    self.i2c.int_fl1.write(|w| w.bits(0x3));
+                }
+            }
+
+            /// Returns the error flagged by the hardware, if any
+            fn fault(&self) -> Option<Error> {
+                let flags = self.i2c.int_fl0.read();
+                let fifo_flags = self.i2c.int_fl1.read();
+
+                if flags.arb_er().bit() {
+                    Some(Error::ArbitrationLost)
+                } else if flags.addr_nack_er().bit() {
+                    Some(Error::AddressNack)
+                } else if flags.data_er().bit() {
+                    Some(Error::DataNack)
+                } else if flags.to_er().bit() {
+                    Some(Error::Timeout)
+                } else if flags.do_not_resp_er().bit() {
+                    Some(Error::DoNotRespond)
+                } else if flags.start_er().bit() {
+                    Some(Error::Start)
+                } else if flags.stop_er().bit() {
+                    Some(Error::Stop)
+                } else if fifo_flags.rx_overflow().bit() {
+                    Some(Error::RxOverflow)
+                } else if fifo_flags.tx_underflow().bit() {
+                    Some(Error::TxUnderflow)
+                } else {
+                    None
+                }
+            }
+
+            fn check(&self) -> Result<(), Error> {
+                match self.fault() {
+                    Some(fault) => Err(fault),
+                    None => Ok(()),
+                }
+            }
+
+            /// Address bytes sent after (repeated) START. A 10-bit read only sends the first
+            /// byte, after a write header in the same transfer.
+            fn header(address: Address, read: bool) -> ([u8; 2], usize) {
+                match address {
+                    Address::Seven(address) => {
+                        let rw = if read { READ_BIT } else { WRITE_BIT };
+                        ([address << 1 | rw, 0], 1)
+                    }
+                    Address::Ten(address) => {
+                        let bytes = Self::_10bit_address_to_bytes(address, read);
+                        (bytes, if read { 1 } else { 2 })
+                    }
+                }
+            }
+
+            /// Sets MASTER_CTRL.SL_EX_ADDR for the address width of the next transfer
+            fn set_address_width(&self, ten_bit: bool) {
+                unsafe {
                     self.i2c.master_ctrl.modify(|r, w| {
-                        w.bits(r.bits()).start().set_bit()
+                        w.bits(r.bits()).sl_ex_addr().bit(ten_bit)
                     });
-        
-                    // Keep adding data as needed
-                    let mut keep_buffering = self.i2c.status.read().tx_empty().bit_is_clear();
-                    while keep_buffering {
-                        if data_offset < data.len() {
-                            data_offset = self.buffer_tx_data(data, &mut data_offset);
-                        }
-        
-                        if self.i2c.status.read().status().is_tx() {
-                            keep_buffering = true;
+                }
+            }
+
+            fn start(&self, restart: bool) {
+                unsafe {
+                    self.i2c.master_ctrl.modify(|r, w| {
+                        if restart {
+                            w.bits(r.bits()).restart().set_bit()
                         } else {
-                            keep_buffering = false;
-                        }
-                        
-                        if self.i2c.status.read().status().is_nack() {
-                            self.master_stop();
-                            return Err(I2CWriteError::NACK);
+                            w.bits(r.bits()).start().set_bit()
                         }
-                    }
-        
-                    self.master_stop();
-                    
-                    Ok(())
+                    });
                 }
             }
-        
-            pub fn master_read(&mut self, address: u16, bytes_to_read: u8, _10_bit: bool) -> Result<usize, I2CReadError> {
-                // See Max32660 User Guide Section 14.4.6.1 I2C Master Receiver Operation
-        
-                self.busy = true;
-                self.bytes_read = 0;
-        
-                // Clear all interrupts
+
+            /// Queues the write header and sends (repeated) START
+            fn start_write(&self, address: Address, restart: bool) {
+                let (header, header_len) = Self::header(address, false);
+
                 unsafe {
-                    self.i2c.int_fl0.modify(|r, w| {
-                        w.bits(r.bits())
-                    });
+                    for byte in &header[..header_len] {
+                        self.i2c.fifo.write(|w| w.data().bits(*byte));
+                    }
                 }
-        
-                let mut bytes_to_read_adjusted: usize = bytes_to_read as usize;
-                if bytes_to_read == 0 {
-                    bytes_to_read_adjusted = 256;
+                self.start(restart);
+            }
+
+            /// Hands `data` to the TX FIFO as room frees up
+            fn send(&mut self, data: &[u8]) -> Result<(), Error> {
+                let mut offset = 0;
+                while offset < data.len() {
+                    self.check()?;
+                    offset = self.buffer_tx_data(data, &mut offset);
                 }
-        
+                Ok(())
+            }
+
+            /// Waits for the TX FIFO to drain before the next repeated START
+            fn drain(&self) -> Result<(), Error> {
+                while !self.i2c.status.read().tx_empty().bit() {
+                    self.check()?;
+                }
+                Ok(())
+            }
+
+            /// Sets RX_CNT to `len`, queues the read header and sends (repeated) START
+            fn start_read(&self, address: Address, len: usize, restart: bool) {
+                let (header, header_len) = Self::header(address, true);
+
                 unsafe {
-                    // 1. Write number of bytes to fetch
+                    // 256 wraps to 0, which RX_CNT takes as 256
                     self.i2c.rx_ctrl1.modify(|r, w| {
-                        w.bits(r.bits()).rx_cnt().bits(bytes_to_read)
+                        w.bits(r.bits()).rx_cnt().bits(len as u8)
                     });
-        
-                    // Start TX of Address with R/W bit set to 1
+
+                    for byte in &header[..header_len] {
+                        self.i2c.fifo.write(|w| w.data().bits(*byte));
+                    }
+                }
+                self.start(restart);
+            }
+
+            /// Fills `buffer` from the RX FIFO
+            fn receive(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+                let mut count = 0;
+                while count < buffer.len() {
+                    self.check()?;
+                    if !self.i2c.status.read().rx_empty().bit() {
+                        buffer[count] = self.i2c.fifo.read().data().bits();
+                        count += 1;
+                    }
+                }
+                Ok(())
+            }
+
+            fn send_stop(&self) {
+                unsafe {
                     self.i2c.master_ctrl.modify(|r, w| {
-                        w.bits(r.bits()).start().set_bit()
+                        w.bits(r.bits()).stop().set_bit()
                     });
-        
-                    // Write address to TX FIFO
-                    if _10_bit {
-                        let address_as_array = Self::_10bit_address_to_bytes(address, true);
-        
-                        self.i2c.master_ctrl.modify(|r, w| {
-                            w.bits(r.bits()).sl_ex_addr().set_bit()
-                        });
-        
-                        for byte in address_as_array {
-                            self.i2c.fifo.write(|w| w.data().bits(byte));
+                }
+            }
+
+            /// Sends STOP and waits for it, reporting a NACK of the last written byte
+            fn finish(&mut self) -> Result<(), Error> {
+                self.send_stop();
+
+                while !self.i2c.int_fl0.read().stop().bit() {
+                    self.check()?;
+                }
+                self.check()?;
+
+                self.busy = false;
+                self.clear_flags();
+                Ok(())
+            }
+
+            /// Runs `operations` between one START and STOP. Adjacent operations of the same
+            /// kind are merged, a repeated START separates writes from reads.
+            fn execute(&mut self, address: Address, operations: &mut [Operation]) -> Result<(), Error> {
+                if self.transfer.is_some() {
+                    return Err(Error::Busy);
+                }
+                self.busy = true;
+                self.clear_flags();
+                self.set_address_width(matches!(address, Address::Ten(_)));
+
+                let result = self.execute_operations(address, operations);
+                let result = result.and_then(|_| self.finish());
+
+                if result.is_err() {
+                    // Without a delay to bound the wait, STOP is only requested. A bus that
+                    // stays stuck fails the next transfer.
+                    self.abort();
+                    self.clear_flags();
+                }
+                result
+            }
+
+            fn execute_operations(&mut self, address: Address, operations: &mut [Operation]) -> Result<(), Error> {
+                let mut started = false;
+                let mut index = 0;
+
+                while index < operations.len() {
+                    let reading = matches!(operations[index], Operation::Read(_));
+                    let end = operations[index..]
+                        .iter()
+                        .position(|operation| matches!(operation, Operation::Read(_)) != reading)
+                        .map_or(operations.len(), |len| index + len);
+                    let run = &mut operations[index..end];
+                    index = end;
+
+                    if !reading {
+                        if started {
+                            self.drain()?;
+                        }
+                        self.start_write(address, started);
+                        started = true;
+
+                        for operation in run.iter() {
+                            if let Operation::Write(bytes) = operation {
+                                self.send(bytes)?;
+                            }
+                        }
+                        continue;
+                    }
+
+                    let len: usize = run
+                        .iter()
+                        .map(|operation| match operation {
+                            Operation::Read(buffer) => buffer.len(),
+                            Operation::Write(_) => 0,
+                        })
+                        .sum();
+                    if len > MAX_READ_LEN {
+                        return Err(Error::BufferTooLong);
+                    }
+                    // RX_CNT can't count zero bytes
+                    if len == 0 {
+                        continue;
+                    }
+
+                    // A 10-bit read needs the full address sent in write direction first
+                    if !started && matches!(address, Address::Ten(_)) {
+                        self.start_write(address, false);
+                        started = true;
+                    }
+                    if started {
+                        self.drain()?;
+                    }
+                    self.start_read(address, len, started);
+                    started = true;
+
+                    for operation in run.iter_mut() {
+                        if let Operation::Read(buffer) = operation {
+                            self.receive(buffer)?;
                         }
-                    }else{
-                        let address_u8 = (address as u8) << 1;
-                        self.i2c.fifo.write(|w| w.data().bits(address_u8 | READ_BIT));
                     }
                 }
-        
-                let mut timeout = 0;
-                while self.bytes_read < bytes_to_read as usize {
-        
-                    if self.i2c.int_fl0.read().addr_nack_er().bit() ||
-                        self.i2c.int_fl0.read().arb_er().bit() ||
-                        self.i2c.int_fl0.read().data_er().bit() ||
-                        self.i2c.int_fl0.read().do_not_resp_er().bit() ||
-                        self.i2c.int_fl0.read().start_er().bit() ||
-                        self.i2c.int_fl0.read().stop_er().bit() ||
-                        self.i2c.int_fl0.read().to_er().bit()
-                    {
-                        self.master_stop();
-                        return Err(I2CReadError::UnspecifiedError);
+
+                // Still address the device if there was nothing to transfer
+                if !started {
+                    self.start_write(address, false);
+                }
+
+                Ok(())
+            }
+
+            /// Writes `bytes`, then reads `buffer` after a repeated START, for the usual
+            /// register read of sensors
+            pub fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Error> {
+                let mut operations = [Operation::Write(bytes), Operation::Read(buffer)];
+                self.execute(Address::Seven(address), &mut operations)
+            }
+
+            /// Runs a sequence of reads and writes to the 7-bit `address` in one transfer.
+            /// Adjacent operations of the same kind are merged, a repeated START separates
+            /// writes from reads and a STOP ends the transfer. Adjacent reads can receive up
+            /// to 256 bytes together.
+            pub fn transaction(&mut self, address: u8, operations: &mut [Operation]) -> Result<(), Error> {
+                self.execute(Address::Seven(address), operations)
+            }
+
+            /// `transaction` to a 10-bit `address`
+            pub fn transaction_10bit(&mut self, address: u16, operations: &mut [Operation]) -> Result<(), Error> {
+                self.execute(Address::Ten(address), operations)
+            }
+        }
+
+        impl $name {
+            /// Starts writing `bytes`, then reading `buffer` after a repeated START, driven
+            /// by the I2C interrupt. Call `on_interrupt` from the I2C interrupt handler, the
+            /// result is then reported by the completion callback and `take_completed`.
+            /// Either of `bytes` and `buffer` can be empty.
+            ///
+            /// `on_interrupt` and this function must not interrupt each other, e.g. share
+            /// the I2C through a critical section mutex.
+            pub fn begin_write_read(&mut self, address: u8, bytes: &'static [u8], buffer: &'static mut [u8]) -> Result<(), Error> {
+                if self.busy {
+                    return Err(Error::Busy);
+                }
+                if buffer.len() > MAX_READ_LEN {
+                    return Err(Error::BufferTooLong);
+                }
+
+                self.busy = true;
+                self.completed = None;
+                self.clear_flags();
+
+                self.set_address_width(false);
+
+                let address = Address::Seven(address);
+                let phase = if bytes.is_empty() && !buffer.is_empty() {
+                    self.start_read(address, buffer.len(), false);
+                    Phase::Reading
+                } else {
+                    self.start_write(address, false);
+                    Phase::Writing
+                };
+
+                self.transfer = Some(IrqTransfer {
+                    address,
+                    bytes,
+                    written: 0,
+                    buffer,
+                    read: 0,
+                    phase,
+                });
+
+                // Queues the first bytes and enables the interrupts of the phase
+                self.on_interrupt();
+                Ok(())
+            }
+
+            /// Starts an interrupt-driven write, see `begin_write_read`
+            pub fn begin_write(&mut self, address: u8, bytes: &'static [u8]) -> Result<(), Error> {
+                self.begin_write_read(address, bytes, &mut [])
+            }
+
+            /// Starts an interrupt-driven read, see `begin_write_read`
+            pub fn begin_read(&mut self, address: u8, buffer: &'static mut [u8]) -> Result<(), Error> {
+                self.begin_write_read(address, &[], buffer)
+            }
+
+            /// Sets the function called when an interrupt-driven transfer ends
+            pub fn set_completion_callback(&mut self, callback: CompletionCallback) -> &mut Self {
+                self.callback = Some(callback);
+                self
+            }
+
+            /// Returns the result and read buffer of the last interrupt-driven transfer once
+            /// it has ended
+            pub fn take_completed(&mut self) -> Option<(Result<(), Error>, &'static mut [u8])> {
+                self.completed.take()
+            }
+
+            /// Advances the interrupt-driven transfer, call from the I2C interrupt handler
+            pub fn on_interrupt(&mut self) {
+                let mut transfer = match self.transfer.take() {
+                    Some(transfer) => transfer,
+                    None => return,
+                };
+
+                let result = match self.service(&mut transfer) {
+                    Ok(false) => {
+                        self.transfer = Some(transfer);
+                        return;
                     }
-        
-                    if !self.i2c.status.read().rx_empty().bit() {
-                        self.buffer[self.bytes_read] = self.i2c.fifo.read().data().bits();
-                        self.bytes_read += 1;
+                    Ok(true) => Ok(()),
+                    Err(error) => {
+                        // Waiting for the bus to go idle has no place in an interrupt
+                        self.abort();
+                        Err(error)
                     }
-        
-                    timeout += 1;
-                    if timeout > 128 {
-                        self.master_stop();
-                        return Err(I2CReadError::Timeout);
+                };
+
+                unsafe {
+                    self.i2c.int_en0.write(|w| w.bits(0));
+                }
+                self.clear_flags();
+                self.busy = false;
+
+                if let Some(callback) = self.callback {
+                    callback(result);
+                }
+                self.completed = Some((result, transfer.buffer));
+            }
+
+            /// Runs the state machine as far as the FIFOs allow, returns true once STOP
+            /// has been sent
+            fn service(&mut self, transfer: &mut IrqTransfer) -> Result<bool, Error> {
+                self.i2c.int_fl0.write(|w| w.done().set_bit());
+                self.check()?;
+
+                loop {
+                    match transfer.phase {
+                        Phase::Writing => {
+                            transfer.written = self.buffer_tx_data(transfer.bytes, &mut transfer.written);
+
+                            // Wait for the FIFO to drain before a repeated START or STOP
+                            if transfer.written < transfer.bytes.len() || !self.i2c.status.read().tx_empty().bit() {
+                                let thresh = if transfer.written < transfer.bytes.len() { IRQ_TX_THRESH } else { 0 };
+                                unsafe {
+                                    self.i2c.tx_ctrl0.modify(|r, w| {
+                                        w.bits(r.bits()).tx_thresh().bits(thresh)
+                                    });
+                                }
+                                // The flag is write-1-to-clear, a stale one would fire again
+                                // right away
+                                self.i2c.int_fl0.write(|w| w.tx_thresh().set_bit());
+                                self.listen(Interrupts0::TxThresh as u32);
+                                return Ok(false);
+                            }
+
+                            if transfer.buffer.is_empty() {
+                                self.send_stop();
+                                transfer.phase = Phase::Stopping;
+                            } else {
+                                self.start_read(transfer.address, transfer.buffer.len(), true);
+                                transfer.phase = Phase::Reading;
+                            }
+                        }
+                        Phase::Reading => {
+                            while transfer.read < transfer.buffer.len() && !self.i2c.status.read().rx_empty().bit() {
+                                transfer.buffer[transfer.read] = self.i2c.fifo.read().data().bits();
+                                transfer.read += 1;
+                            }
+
+                            let remaining = transfer.buffer.len() - transfer.read;
+                            if remaining > 0 {
+                                unsafe {
+                                    self.i2c.rx_ctrl0.modify(|r, w| {
+                                        w.bits(r.bits()).rx_thresh().bits(remaining.min(IRQ_RX_THRESH) as u8)
+                                    });
+                                }
+                                self.i2c.int_fl0.write(|w| w.rx_thresh().set_bit());
+                                self.listen(Interrupts0::RxThresh as u32);
+                                return Ok(false);
+                            }
+
+                            self.send_stop();
+                            transfer.phase = Phase::Stopping;
+                        }
+                        Phase::Stopping => {
+                            if self.i2c.int_fl0.read().stop().bit() {
+                                return Ok(true);
+                            }
+                            self.listen(Interrupts0::Stop as u32);
+                            return Ok(false);
+                        }
                     }
                 }
-        
-                self.master_stop();
-        
-                Ok(self.bytes_read)
-                
             }
-        
-            //pub fn 
-        
+
+            /// Enables `ints` along with the error interrupts
+            fn listen(&self, ints: u32) {
+                unsafe {
+                    self.i2c.int_en0.write(|w| w.bits(ints | IRQ_ERRORS));
+                }
+            }
+        }
+
+        impl Slave for $name {
+            fn poll(&mut self) -> Option<SlaveEvent> {
+                self.slave_poll()
+            }
+
+            fn respond(&mut self, data: &[u8]) -> usize {
+                self.slave_respond(data)
+            }
+
+            fn take_unsent(&mut self) -> usize {
+                self.slave_take_unsent()
+            }
+        }
+
+        impl Write<SevenBitAddress> for $name {
+            type Error = Error;
+
+            fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Error> {
+                self.execute(Address::Seven(address), &mut [Operation::Write(bytes)])
+            }
+        }
+
+        impl Write<TenBitAddress> for $name {
+            type Error = Error;
+
+            fn write(&mut self, address: u16, bytes: &[u8]) -> Result<(), Error> {
+                self.execute(Address::Ten(address), &mut [Operation::Write(bytes)])
+            }
+        }
+
+        impl Read<SevenBitAddress> for $name {
+            type Error = Error;
+
+            fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Error> {
+                self.execute(Address::Seven(address), &mut [Operation::Read(buffer)])
+            }
+        }
+
+        impl Read<TenBitAddress> for $name {
+            type Error = Error;
+
+            fn read(&mut self, address: u16, buffer: &mut [u8]) -> Result<(), Error> {
+                self.execute(Address::Ten(address), &mut [Operation::Read(buffer)])
+            }
+        }
+
+        impl WriteRead<SevenBitAddress> for $name {
+            type Error = Error;
+
+            fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Error> {
+                $name::write_read(self, address, bytes, buffer)
+            }
+        }
+
+        impl WriteRead<TenBitAddress> for $name {
+            type Error = Error;
+
+            fn write_read(&mut self, address: u16, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Error> {
+                let mut operations = [Operation::Write(bytes), Operation::Read(buffer)];
+                self.execute(Address::Ten(address), &mut operations)
+            }
+        }
+
+        impl Transactional<SevenBitAddress> for $name {
+            type Error = Error;
+
+            fn exec(&mut self, address: u8, operations: &mut [Operation]) -> Result<(), Error> {
+                $name::transaction(self, address, operations)
+            }
+        }
+
+        impl Transactional<TenBitAddress> for $name {
+            type Error = Error;
+
+            fn exec(&mut self, address: u16, operations: &mut [Operation]) -> Result<(), Error> {
+                self.transaction_10bit(address, operations)
+            }
         }
     };
 }
 
-i2c!(max32660_pac::I2C0, I2c0, 8);
-i2c!(max32660_pac::I2C1, I2c1, 8);
\ No newline at end of file
+i2c!(max32660_pac::I2C0, I2c0, Pins::_8, Pins::_9);
+i2c!(max32660_pac::I2C1, I2c1, Pins::_2, Pins::_3);
\ No newline at end of file
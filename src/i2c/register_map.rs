@@ -0,0 +1,306 @@
+//! Register-based I2C slave, making the MAX32660 look like a typical sensor to the host.
+//!
+//! The first byte of a write sets the register pointer, following bytes are written to
+//! the registers from there on. Reads return the registers from the pointer on. The
+//! pointer increments after every byte the master writes or reads and wraps around at
+//! the end of the map. A pointer past the end of the map stays there, its registers read
+//! as 0 and ignore writes. Writes to the general call address are ignored.
+//!
+//! ```ignore
+//! i2c.slave_mode(SlaveAddress::Seven(0x48), false);
+//! let mut slave = RegisterMapSlave::<_, 16>::new(i2c);
+//! slave.set_write_mask(WHO_AM_I, 0x00)?.on_write(apply_config);
+//! loop {
+//!     slave.registers_mut()[TEMPERATURE] = read_temperature();
+//!     slave.poll();
+//! }
+//! ```
+
+use super::{Slave, SlaveEvent};
+
+/// Called after the master wrote `register`, with all registers to act on
+pub type WriteCallback<const N: usize> = fn(register: u8, registers: &mut [u8; N]);
+
+#[derive(Debug)]
+pub enum RegisterMapError {
+    /// The register is past the end of the map
+    OutOfRange,
+}
+
+pub struct RegisterMapSlave<S, const N: usize> {
+    i2c: S,
+    registers: [u8; N],
+    /// Bits the master can write, the others keep their value
+    write_masks: [u8; N],
+    /// Bits the master can read, the others read as 0
+    read_masks: [u8; N],
+    on_write: Option<WriteCallback<N>>,
+    pointer: usize,
+    /// The next byte written sets the pointer
+    expect_pointer: bool,
+    /// The master is writing to the general call address, its bytes are dropped
+    general_call: bool,
+}
+
+impl<S: Slave, const N: usize> RegisterMapSlave<S, N> {
+    /// Creates a map of `N` registers, all zero and fully readable and writable. `i2c`
+    /// has to be in slave mode.
+    pub fn new(i2c: S) -> Self {
+        assert!(N > 0 && N <= 256, "N should be 1 to 256");
+
+        RegisterMapSlave {
+            i2c,
+            registers: [0; N],
+            write_masks: [0xFF; N],
+            read_masks: [0xFF; N],
+            on_write: None,
+            pointer: 0,
+            expect_pointer: false,
+            general_call: false,
+        }
+    }
+
+    /// Releases the I2C peripheral
+    pub fn free(self) -> S {
+        self.i2c
+    }
+
+    pub fn registers(&self) -> &[u8; N] {
+        &self.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut [u8; N] {
+        &mut self.registers
+    }
+
+    /// Sets the bits of `register` the master can write, 0x00 makes it read-only
+    pub fn set_write_mask(
+        &mut self,
+        register: u8,
+        mask: u8,
+    ) -> Result<&mut Self, RegisterMapError> {
+        *self
+            .write_masks
+            .get_mut(register as usize)
+            .ok_or(RegisterMapError::OutOfRange)? = mask;
+        Ok(self)
+    }
+
+    /// Sets the bits of `register` the master can read, 0x00 makes it write-only
+    pub fn set_read_mask(&mut self, register: u8, mask: u8) -> Result<&mut Self, RegisterMapError> {
+        *self
+            .read_masks
+            .get_mut(register as usize)
+            .ok_or(RegisterMapError::OutOfRange)? = mask;
+        Ok(self)
+    }
+
+    /// Sets the function called after each register written by the master
+    pub fn on_write(&mut self, callback: WriteCallback<N>) -> &mut Self {
+        self.on_write = Some(callback);
+        self
+    }
+
+    /// Handles all pending bus events. Call it from a loop or the I2C interrupt.
+    ///
+    /// Returns true if the master wrote to a register.
+    pub fn poll(&mut self) -> bool {
+        let mut written = false;
+
+        while let Some(event) = self.i2c.poll() {
+            match event {
+                SlaveEvent::WriteRequest { general_call } => {
+                    self.expect_pointer = !general_call;
+                    self.general_call = general_call;
+                }
+                SlaveEvent::ByteReceived(_) if self.general_call => {}
+                SlaveEvent::ByteReceived(byte) => {
+                    if self.expect_pointer {
+                        self.expect_pointer = false;
+                        self.pointer = byte as usize;
+                    } else {
+                        written |= self.write(byte);
+                    }
+                }
+                SlaveEvent::ByteRequested => {
+                    let value = if self.pointer < N {
+                        self.registers[self.pointer] & self.read_masks[self.pointer]
+                    } else {
+                        0
+                    };
+                    if self.i2c.respond(&[value]) == 1 {
+                        self.advance();
+                    }
+                }
+                SlaveEvent::ReadRequest | SlaveEvent::Stop => {
+                    self.expect_pointer = false;
+                    self.general_call = false;
+                    // Bytes are queued ahead of the master, step back over those it
+                    // didn't read
+                    let unsent = self.i2c.take_unsent() % N;
+                    if self.pointer < N {
+                        self.pointer = (self.pointer + N - unsent) % N;
+                    }
+                }
+            }
+        }
+
+        written
+    }
+
+    /// Writes `value` to the register at the pointer, returns false if it's past the end
+    fn write(&mut self, value: u8) -> bool {
+        let register = self.pointer;
+        if register >= N {
+            return false;
+        }
+
+        let mask = self.write_masks[register];
+        self.registers[register] = (self.registers[register] & !mask) | (value & mask);
+        self.advance();
+
+        if let Some(callback) = self.on_write {
+            callback(register as u8, &mut self.registers);
+        }
+        true
+    }
+
+    fn advance(&mut self) {
+        if self.pointer < N {
+            self.pointer = (self.pointer + 1) % N;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+
+    /// Plays scripted bus events and records what the slave queued for the master
+    #[derive(Default)]
+    struct FakeSlave {
+        events: VecDeque<SlaveEvent>,
+        sent: Vec<u8>,
+        unsent: usize,
+    }
+
+    impl Slave for FakeSlave {
+        fn poll(&mut self) -> Option<SlaveEvent> {
+            self.events.pop_front()
+        }
+
+        fn respond(&mut self, data: &[u8]) -> usize {
+            self.sent.extend_from_slice(data);
+            data.len()
+        }
+
+        fn take_unsent(&mut self) -> usize {
+            core::mem::take(&mut self.unsent)
+        }
+    }
+
+    fn master_write(
+        slave: &mut RegisterMapSlave<FakeSlave, 4>,
+        general_call: bool,
+        bytes: &[u8],
+    ) -> bool {
+        let events = &mut slave.i2c.events;
+        events.push_back(SlaveEvent::WriteRequest { general_call });
+        events.extend(bytes.iter().map(|byte| SlaveEvent::ByteReceived(*byte)));
+        events.push_back(SlaveEvent::Stop);
+        slave.poll()
+    }
+
+    /// The slave queues `queued` bytes of which the master reads all but `unsent`
+    fn master_read(
+        slave: &mut RegisterMapSlave<FakeSlave, 4>,
+        queued: usize,
+        unsent: usize,
+    ) -> Vec<u8> {
+        slave.i2c.events.push_back(SlaveEvent::ReadRequest);
+        slave.poll();
+        slave
+            .i2c
+            .events
+            .extend((0..queued).map(|_| SlaveEvent::ByteRequested));
+        slave.poll();
+        slave.i2c.unsent = unsent;
+        slave.i2c.events.push_back(SlaveEvent::Stop);
+        slave.poll();
+        let sent = core::mem::take(&mut slave.i2c.sent);
+        sent[..queued - unsent].to_vec()
+    }
+
+    #[test]
+    fn writes_and_reads_auto_increment_and_wrap() {
+        let mut slave = RegisterMapSlave::<_, 4>::new(FakeSlave::default());
+        assert!(master_write(&mut slave, false, &[2, 0xA, 0xB, 0xC]));
+        assert_eq!(slave.registers(), &[0xC, 0, 0xA, 0xB]);
+
+        master_write(&mut slave, false, &[3]);
+        assert_eq!(master_read(&mut slave, 3, 0), [0xB, 0xC, 0]);
+    }
+
+    #[test]
+    fn masks_limit_writes_and_reads() {
+        let mut slave = RegisterMapSlave::<_, 4>::new(FakeSlave::default());
+        slave
+            .set_write_mask(0, 0x0F)
+            .unwrap()
+            .set_read_mask(1, 0xF0)
+            .unwrap();
+        slave.registers_mut()[0] = 0xA0;
+        master_write(&mut slave, false, &[0, 0xFF, 0xFF]);
+        assert_eq!(slave.registers()[..2], [0xAF, 0xFF]);
+
+        master_write(&mut slave, false, &[0]);
+        assert_eq!(master_read(&mut slave, 2, 0), [0xAF, 0xF0]);
+        assert!(matches!(
+            slave.set_read_mask(4, 0),
+            Err(RegisterMapError::OutOfRange)
+        ));
+    }
+
+    #[test]
+    fn callback_runs_after_each_register() {
+        fn double(register: u8, registers: &mut [u8; 4]) {
+            if register == 1 {
+                registers[3] = registers[1] * 2;
+            }
+        }
+
+        let mut slave = RegisterMapSlave::<_, 4>::new(FakeSlave::default());
+        slave.on_write(double);
+        master_write(&mut slave, false, &[1, 21]);
+        assert_eq!(slave.registers(), &[0, 21, 0, 42]);
+    }
+
+    #[test]
+    fn unsent_bytes_rewind_the_pointer() {
+        let mut slave = RegisterMapSlave::<_, 4>::new(FakeSlave::default());
+        slave.registers_mut().copy_from_slice(&[1, 2, 3, 4]);
+        master_write(&mut slave, false, &[0]);
+
+        // The slave queued three bytes ahead, the master only read one
+        assert_eq!(master_read(&mut slave, 3, 2), [1]);
+        assert_eq!(master_read(&mut slave, 2, 0), [2, 3]);
+    }
+
+    #[test]
+    fn general_call_writes_are_ignored() {
+        let mut slave = RegisterMapSlave::<_, 4>::new(FakeSlave::default());
+        assert!(!master_write(&mut slave, true, &[1, 0x55]));
+        assert_eq!(slave.registers(), &[0; 4]);
+    }
+
+    #[test]
+    fn out_of_range_pointer_reads_zero_and_ignores_writes() {
+        let mut slave = RegisterMapSlave::<_, 4>::new(FakeSlave::default());
+        slave.registers_mut().copy_from_slice(&[1, 2, 3, 4]);
+        assert!(!master_write(&mut slave, false, &[4, 0x55, 0x66]));
+        assert_eq!(slave.registers(), &[1, 2, 3, 4]);
+        assert_eq!(master_read(&mut slave, 2, 1), [0]);
+    }
+}
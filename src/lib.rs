@@ -1,7 +1,16 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
+pub mod crc;
+pub mod dma;
+pub mod dmx;
+pub mod framing;
 pub mod gpio;
 pub mod i2c;
+pub mod lin;
+pub mod modbus;
+pub mod rs485;
+pub mod shell;
 pub mod sys;
 pub mod timer;
 pub mod uart;
+pub mod xmodem;
@@ -0,0 +1,356 @@
+//! LIN master and slave built on top of `Uart0`/`Uart1`.
+//!
+//! The UART must already be enabled and set to the bus baud rate with 8N1 framing.
+//! The LIN transceiver loops TX back to RX, every transmitted byte is read back and
+//! compared to detect bit errors.
+
+use embedded_hal::blocking::delay::DelayUs;
+use embedded_hal::serial::{Read, Write};
+
+use crate::uart::{BreakControl, UartError};
+
+const SYNC: u8 = 0x55;
+
+/// Break length in bit times, LIN requires at least 13
+const BREAK_BITS: u32 = 13;
+const BREAK_DELIMITER_BITS: u32 = 1;
+
+/// Nominal header length in bit times (break, delimiter, sync and PID)
+const HEADER_BITS: u32 = 34;
+
+/// Identifiers of the diagnostic frames, these always use the classic checksum
+const MASTER_REQUEST_ID: u8 = 0x3C;
+const SLAVE_RESPONSE_ID: u8 = 0x3D;
+
+pub const MAX_ID: u8 = 0x3F;
+pub const MAX_DATA_LEN: usize = 8;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Checksum {
+    /// LIN 1.x, data bytes only
+    Classic,
+    /// LIN 2.x, data bytes and protected identifier
+    Enhanced,
+}
+
+#[derive(Debug)]
+pub enum LinError {
+    Uart(UartError),
+    /// The byte read back from the bus differs from the one transmitted
+    BitError,
+    /// The frame was not completed within the maximum LIN frame time
+    Timeout,
+    Checksum,
+    /// The byte following a break was not the 0x55 sync field
+    Sync,
+    /// The received protected identifier has invalid parity bits
+    Parity,
+    InvalidId,
+    InvalidLength,
+}
+
+/// Computes the protected identifier (identifier plus parity bits P0 and P1)
+pub fn protected_id(id: u8) -> u8 {
+    let id = id & MAX_ID;
+    let bit = |n: u8| (id >> n) & 0x1;
+
+    let p0 = bit(0) ^ bit(1) ^ bit(2) ^ bit(4);
+    let p1 = !(bit(1) ^ bit(3) ^ bit(4) ^ bit(5)) & 0x1;
+
+    id | (p0 << 6) | (p1 << 7)
+}
+
+/// Computes the inverted sum-with-carry checksum of a frame response
+pub fn checksum(model: Checksum, pid: u8, data: &[u8]) -> u8 {
+    let mut sum: u16 = match model {
+        Checksum::Classic => 0,
+        Checksum::Enhanced => pid as u16,
+    };
+
+    for byte in data {
+        sum += *byte as u16;
+        if sum > 0xFF {
+            sum -= 0xFF;
+        }
+    }
+
+    !(sum as u8)
+}
+
+fn frame_checksum(model: Checksum, pid: u8, data: &[u8]) -> u8 {
+    let id = pid & MAX_ID;
+    if id == MASTER_REQUEST_ID || id == SLAVE_RESPONSE_ID {
+        checksum(Checksum::Classic, pid, data)
+    } else {
+        checksum(model, pid, data)
+    }
+}
+
+/// Duration of `bits` bit times at `baud`, rounded up
+fn bits_to_us(bits: u32, baud: u32) -> u32 {
+    (bits as u64 * 1_000_000).div_ceil(baud as u64) as u32
+}
+
+/// Maximum response time in bit times, the nominal time plus the 40% LIN tolerance
+fn response_max_bits(len: usize) -> u32 {
+    14 * (len as u32 + 1)
+}
+
+fn check_len(len: usize) -> Result<(), LinError> {
+    if len == 0 || len > MAX_DATA_LEN {
+        Err(LinError::InvalidLength)
+    } else {
+        Ok(())
+    }
+}
+
+/// Byte level access shared by the master and slave
+struct Bus<S, D> {
+    uart: S,
+    delay: D,
+    bit_time_us: u32,
+    /// Break and delimiter lengths, rounded up so they are never shorter than required
+    break_us: u32,
+    delimiter_us: u32,
+}
+
+impl<S, D> Bus<S, D>
+where
+    S: Read<u8, Error = UartError> + Write<u8, Error = UartError> + BreakControl,
+    D: DelayUs<u32>,
+{
+    fn new(uart: S, delay: D, baud: u32) -> Self {
+        Bus {
+            uart,
+            delay,
+            bit_time_us: (1_000_000 + baud / 2) / baud,
+            break_us: bits_to_us(BREAK_BITS, baud),
+            delimiter_us: bits_to_us(BREAK_DELIMITER_BITS, baud),
+        }
+    }
+
+    /// Reads a byte, polling once per bit time and charging each poll to `budget`
+    fn read_byte(&mut self, budget: &mut u32) -> Result<u8, LinError> {
+        loop {
+            match self.uart.read() {
+                Ok(byte) => return Ok(byte),
+                Err(nb::Error::Other(e)) => return Err(LinError::Uart(e)),
+                Err(nb::Error::WouldBlock) => {
+                    if *budget == 0 {
+                        return Err(LinError::Timeout);
+                    }
+                    *budget -= 1;
+                    self.delay.delay_us(self.bit_time_us);
+                }
+            }
+        }
+    }
+
+    /// Writes a byte and checks the echo read back from the transceiver
+    fn write_byte(&mut self, byte: u8) -> Result<(), LinError> {
+        nb::block!(self.uart.write(byte)).map_err(LinError::Uart)?;
+
+        // One character plus some margin for the transceiver loop delay
+        let mut budget = 2 * 10;
+        match self.read_byte(&mut budget) {
+            Ok(echo) if echo == byte => Ok(()),
+            Ok(_) | Err(LinError::Uart(UartError::Break)) => Err(LinError::BitError),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Discards anything left in the RX FIFO
+    fn drain(&mut self) {
+        while !matches!(self.uart.read(), Err(nb::Error::WouldBlock)) {}
+    }
+
+    fn send_break(&mut self) -> Result<(), LinError> {
+        nb::block!(self.uart.flush()).map_err(LinError::Uart)?;
+        self.drain();
+
+        self.uart.set_break(true);
+        self.delay.delay_us(self.break_us);
+        self.uart.set_break(false);
+        self.delay.delay_us(self.delimiter_us);
+
+        // The break must come back as a break, anything else means the bus is disturbed
+        let mut budget = BREAK_BITS + 10;
+        match self.read_byte(&mut budget) {
+            Err(LinError::Uart(UartError::Break)) => Ok(()),
+            Ok(_) => Err(LinError::BitError),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn write_response(&mut self, pid: u8, data: &[u8], model: Checksum) -> Result<(), LinError> {
+        check_len(data.len())?;
+
+        for byte in data {
+            self.write_byte(*byte)?;
+        }
+        self.write_byte(frame_checksum(model, pid, data))
+    }
+
+    fn read_response(&mut self, pid: u8, data: &mut [u8], model: Checksum) -> Result<(), LinError> {
+        check_len(data.len())?;
+
+        let mut budget = response_max_bits(data.len());
+        for byte in data.iter_mut() {
+            *byte = self.read_byte(&mut budget)?;
+        }
+
+        let received = self.read_byte(&mut budget)?;
+        if received != frame_checksum(model, pid, data) {
+            return Err(LinError::Checksum);
+        }
+        Ok(())
+    }
+}
+
+pub struct LinMaster<S, D> {
+    bus: Bus<S, D>,
+}
+
+impl<S, D> LinMaster<S, D>
+where
+    S: Read<u8, Error = UartError> + Write<u8, Error = UartError> + BreakControl,
+    D: DelayUs<u32>,
+{
+    /// Creates a master on a UART already configured for `baud`
+    pub fn new(uart: S, delay: D, baud: u32) -> Self {
+        LinMaster {
+            bus: Bus::new(uart, delay, baud),
+        }
+    }
+
+    /// Releases the UART and delay
+    pub fn free(self) -> (S, D) {
+        (self.bus.uart, self.bus.delay)
+    }
+
+    /// Sends break, sync and protected identifier, returns the protected identifier
+    fn send_header(&mut self, id: u8) -> Result<u8, LinError> {
+        if id > MAX_ID {
+            return Err(LinError::InvalidId);
+        }
+
+        let pid = protected_id(id);
+        self.bus.send_break()?;
+        self.bus.write_byte(SYNC)?;
+        self.bus.write_byte(pid)?;
+        Ok(pid)
+    }
+
+    /// Sends a header followed by a response published by the master
+    pub fn write_frame(&mut self, id: u8, data: &[u8], model: Checksum) -> Result<(), LinError> {
+        let pid = self.send_header(id)?;
+        self.bus.write_response(pid, data, model)
+    }
+
+    /// Sends a header and reads the slave's response into `data`
+    pub fn read_frame(&mut self, id: u8, data: &mut [u8], model: Checksum) -> Result<(), LinError> {
+        let pid = self.send_header(id)?;
+        self.bus.read_response(pid, data, model)
+    }
+}
+
+pub struct LinSlave<S, D> {
+    bus: Bus<S, D>,
+    pid: u8,
+}
+
+impl<S, D> LinSlave<S, D>
+where
+    S: Read<u8, Error = UartError> + Write<u8, Error = UartError> + BreakControl,
+    D: DelayUs<u32>,
+{
+    /// Creates a slave on a UART already configured for `baud`
+    pub fn new(uart: S, delay: D, baud: u32) -> Self {
+        LinSlave {
+            bus: Bus::new(uart, delay, baud),
+            pid: 0,
+        }
+    }
+
+    /// Releases the UART and delay
+    pub fn free(self) -> (S, D) {
+        (self.bus.uart, self.bus.delay)
+    }
+
+    /// Waits for a header, returns the frame identifier.
+    ///
+    /// Returns `WouldBlock` until a break is received, then blocks for the rest of the
+    /// header. The caller answers with `respond` or `receive` depending on the identifier.
+    pub fn read_header(&mut self) -> nb::Result<u8, LinError> {
+        match self.bus.uart.read() {
+            Err(nb::Error::Other(UartError::Break)) => {}
+            // Data outside a header belongs to frames this slave doesn't take part in
+            Ok(_) | Err(nb::Error::WouldBlock) => return Err(nb::Error::WouldBlock),
+            Err(nb::Error::Other(e)) => return Err(nb::Error::Other(LinError::Uart(e))),
+        }
+
+        let mut budget = (HEADER_BITS - BREAK_BITS) * 14 / 10;
+        if self.bus.read_byte(&mut budget)? != SYNC {
+            return Err(nb::Error::Other(LinError::Sync));
+        }
+
+        let pid = self.bus.read_byte(&mut budget)?;
+        if pid != protected_id(pid) {
+            return Err(nb::Error::Other(LinError::Parity));
+        }
+
+        self.pid = pid;
+        Ok(pid & MAX_ID)
+    }
+
+    /// Publishes the response to the last received header
+    pub fn respond(&mut self, data: &[u8], model: Checksum) -> Result<(), LinError> {
+        self.bus.write_response(self.pid, data, model)
+    }
+
+    /// Reads the response another node publishes for the last received header
+    pub fn receive(&mut self, data: &mut [u8], model: Checksum) -> Result<(), LinError> {
+        self.bus.read_response(self.pid, data, model)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protected_id_adds_parity() {
+        assert_eq!(protected_id(0x3C), 0x3C);
+        assert_eq!(protected_id(0x3D), 0x7D);
+        assert_eq!(protected_id(0x00), 0x80);
+        assert_eq!(protected_id(0x01), 0xC1);
+    }
+
+    #[test]
+    fn classic_checksum_covers_data_only() {
+        assert_eq!(checksum(Checksum::Classic, 0x3C, &[0x01, 0x02, 0x03]), 0xF9);
+        // The carry is added back into the sum
+        assert_eq!(checksum(Checksum::Classic, 0x3C, &[0xFF, 0x02]), 0xFD);
+    }
+
+    #[test]
+    fn enhanced_checksum_includes_pid() {
+        assert_eq!(checksum(Checksum::Enhanced, 0x4A, &[0x55, 0x93, 0xE5]), 0xE6);
+    }
+
+    #[test]
+    fn diagnostic_frames_use_classic_checksum() {
+        let data = [0x01, 0x02, 0x03];
+        assert_eq!(frame_checksum(Checksum::Enhanced, protected_id(0x3C), &data), 0xF9);
+        assert_eq!(frame_checksum(Checksum::Enhanced, protected_id(0x3D), &data), 0xF9);
+    }
+
+    #[test]
+    fn break_is_at_least_13_bit_times() {
+        assert_eq!(bits_to_us(BREAK_BITS, 19200), 678);
+        assert_eq!(bits_to_us(BREAK_BITS, 9600), 1355);
+        for baud in [1000, 2400, 9600, 10417, 19200, 20000] {
+            assert!(bits_to_us(BREAK_BITS, baud) as u64 * baud as u64 >= BREAK_BITS as u64 * 1_000_000);
+        }
+    }
+}
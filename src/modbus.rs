@@ -0,0 +1,906 @@
+//! Modbus RTU slave and master.
+//!
+//! Frames are delimited by line idle time. On `Uart0`/`Uart1` this uses the hardware RX
+//! timeout, which counts whole characters, so set it to 4 character times (the 3.5
+//! character gap rounded up) before use:
+//!
+//! ```ignore
+//! uart.set_rx_timeout(4);
+//! let mut slave = modbus::Slave::new(uart, 0x11, modbus::Registers::<16, 16, 32, 32>::new());
+//! loop {
+//!     slave.poll()?;
+//! }
+//! ```
+//!
+//! Both ends only depend on `Transport`, so they can run on the host against an
+//! in-memory transport.
+
+use embedded_hal::blocking::delay::DelayUs;
+
+use crate::crc;
+use crate::uart::{Interrupts, RxPin, TxPin, Uart0, Uart1, UartError};
+
+/// Maximum size of an RTU frame (address, PDU and CRC)
+pub const MAX_ADU: usize = 256;
+
+const BROADCAST: u8 = 0;
+
+const READ_COILS: u8 = 0x01;
+const READ_DISCRETE_INPUTS: u8 = 0x02;
+const READ_HOLDING_REGISTERS: u8 = 0x03;
+const READ_INPUT_REGISTERS: u8 = 0x04;
+const WRITE_SINGLE_COIL: u8 = 0x05;
+const WRITE_SINGLE_REGISTER: u8 = 0x06;
+const WRITE_MULTIPLE_COILS: u8 = 0x0F;
+const WRITE_MULTIPLE_REGISTERS: u8 = 0x10;
+
+const EXCEPTION_FLAG: u8 = 0x80;
+const COIL_ON: u16 = 0xFF00;
+const COIL_OFF: u16 = 0x0000;
+
+const MAX_READ_BITS: u16 = 2000;
+const MAX_READ_REGISTERS: u16 = 125;
+const MAX_WRITE_BITS: u16 = 1968;
+const MAX_WRITE_REGISTERS: u16 = 123;
+
+/// Default time the master waits for a response
+const DEFAULT_TIMEOUT_US: u32 = 100_000;
+const POLL_INTERVAL_US: u32 = 100;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Exception {
+    IllegalFunction = 0x01,
+    IllegalDataAddress = 0x02,
+    IllegalDataValue = 0x03,
+    ServerDeviceFailure = 0x04,
+    Acknowledge = 0x05,
+    ServerDeviceBusy = 0x06,
+}
+
+impl Exception {
+    fn from_code(code: u8) -> Self {
+        match code {
+            0x01 => Exception::IllegalFunction,
+            0x02 => Exception::IllegalDataAddress,
+            0x03 => Exception::IllegalDataValue,
+            0x05 => Exception::Acknowledge,
+            0x06 => Exception::ServerDeviceBusy,
+            _ => Exception::ServerDeviceFailure,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ModbusError {
+    Uart(UartError),
+    /// No response within the master's timeout
+    Timeout,
+    Crc,
+    /// The response doesn't match the request
+    InvalidResponse,
+    /// The quantity requested is outside what a single Modbus request allows
+    InvalidRequest,
+    /// The slave answered with an exception
+    Exception(Exception),
+}
+
+/// Byte transport with RTU frame delimiting
+pub trait Transport {
+    /// Returns the next received byte
+    fn read(&mut self) -> nb::Result<u8, UartError>;
+
+    /// Returns true, once, after the line has been idle for 3.5 characters following
+    /// received data
+    fn frame_idle(&mut self) -> bool;
+
+    /// Transmits a complete frame, returning once it has left the shift register
+    fn write(&mut self, frame: &[u8]) -> Result<(), UartError>;
+
+    /// Drops all received bytes, including any `read` holds back, and a pending idle
+    fn discard(&mut self);
+}
+
+macro_rules! uart_transport {
+    ($name:ident, $UARTX:ty) => {
+        impl<TX: TxPin<$UARTX>, RX: RxPin<$UARTX>, FC> Transport for $name<TX, RX, FC> {
+            fn read(&mut self) -> nb::Result<u8, UartError> {
+                self.read_before_idle().ok_or(nb::Error::WouldBlock)
+            }
+
+            fn frame_idle(&mut self) -> bool {
+                self.take_idle()
+            }
+
+            fn write(&mut self, frame: &[u8]) -> Result<(), UartError> {
+                self.write_blocking(frame);
+                while !self.tx_idle() {}
+                Ok(())
+            }
+
+            fn discard(&mut self) {
+                self.flush_rx_fifo();
+                self.clear_interrupt(&[Interrupts::RxTimeout]);
+            }
+        }
+    };
+}
+
+uart_transport!(Uart0, max32660_pac::UART0);
+uart_transport!(Uart1, max32660_pac::UART1);
+
+/// CRC-16/MODBUS (polynomial 0xA001 reflected, initial value 0xFFFF)
+pub fn crc16(data: &[u8]) -> u16 {
+    crc::MODBUS.checksum(data)
+}
+
+/// Appends the CRC, low byte first, to the `len` bytes of `frame`, returns the new length
+fn append_crc(frame: &mut [u8], len: usize) -> usize {
+    let crc = crc16(&frame[..len]);
+    frame[len] = crc as u8;
+    frame[len + 1] = (crc >> 8) as u8;
+    len + 2
+}
+
+fn crc_valid(frame: &[u8]) -> bool {
+    let len = frame.len();
+    len >= 4 && crc16(&frame[..len - 2]) == u16::from_le_bytes([frame[len - 2], frame[len - 1]])
+}
+
+fn get_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+fn put_u16(data: &mut [u8], offset: usize, value: u16) {
+    data[offset..offset + 2].copy_from_slice(&value.to_be_bytes());
+}
+
+/// Data model of a slave. Every object defaults to not existing
+pub trait RegisterMap {
+    fn read_coil(&mut self, _address: u16) -> Result<bool, Exception> {
+        Err(Exception::IllegalDataAddress)
+    }
+
+    fn write_coil(&mut self, _address: u16, _value: bool) -> Result<(), Exception> {
+        Err(Exception::IllegalDataAddress)
+    }
+
+    fn read_discrete_input(&mut self, _address: u16) -> Result<bool, Exception> {
+        Err(Exception::IllegalDataAddress)
+    }
+
+    fn read_holding_register(&mut self, _address: u16) -> Result<u16, Exception> {
+        Err(Exception::IllegalDataAddress)
+    }
+
+    fn write_holding_register(&mut self, _address: u16, _value: u16) -> Result<(), Exception> {
+        Err(Exception::IllegalDataAddress)
+    }
+
+    fn read_input_register(&mut self, _address: u16) -> Result<u16, Exception> {
+        Err(Exception::IllegalDataAddress)
+    }
+}
+
+/// Register map backed by arrays, each starting at address 0
+pub struct Registers<
+    const COILS: usize,
+    const DISCRETE_INPUTS: usize,
+    const HOLDING: usize,
+    const INPUT: usize,
+> {
+    pub coils: [bool; COILS],
+    pub discrete_inputs: [bool; DISCRETE_INPUTS],
+    pub holding_registers: [u16; HOLDING],
+    pub input_registers: [u16; INPUT],
+}
+
+impl<const COILS: usize, const DISCRETE_INPUTS: usize, const HOLDING: usize, const INPUT: usize>
+    Registers<COILS, DISCRETE_INPUTS, HOLDING, INPUT>
+{
+    pub fn new() -> Self {
+        Registers {
+            coils: [false; COILS],
+            discrete_inputs: [false; DISCRETE_INPUTS],
+            holding_registers: [0; HOLDING],
+            input_registers: [0; INPUT],
+        }
+    }
+}
+
+impl<const COILS: usize, const DISCRETE_INPUTS: usize, const HOLDING: usize, const INPUT: usize>
+    Default for Registers<COILS, DISCRETE_INPUTS, HOLDING, INPUT>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const COILS: usize, const DISCRETE_INPUTS: usize, const HOLDING: usize, const INPUT: usize>
+    RegisterMap for Registers<COILS, DISCRETE_INPUTS, HOLDING, INPUT>
+{
+    fn read_coil(&mut self, address: u16) -> Result<bool, Exception> {
+        self.coils
+            .get(address as usize)
+            .copied()
+            .ok_or(Exception::IllegalDataAddress)
+    }
+
+    fn write_coil(&mut self, address: u16, value: bool) -> Result<(), Exception> {
+        let coil = self
+            .coils
+            .get_mut(address as usize)
+            .ok_or(Exception::IllegalDataAddress)?;
+        *coil = value;
+        Ok(())
+    }
+
+    fn read_discrete_input(&mut self, address: u16) -> Result<bool, Exception> {
+        self.discrete_inputs
+            .get(address as usize)
+            .copied()
+            .ok_or(Exception::IllegalDataAddress)
+    }
+
+    fn read_holding_register(&mut self, address: u16) -> Result<u16, Exception> {
+        self.holding_registers
+            .get(address as usize)
+            .copied()
+            .ok_or(Exception::IllegalDataAddress)
+    }
+
+    fn write_holding_register(&mut self, address: u16, value: u16) -> Result<(), Exception> {
+        let register = self
+            .holding_registers
+            .get_mut(address as usize)
+            .ok_or(Exception::IllegalDataAddress)?;
+        *register = value;
+        Ok(())
+    }
+
+    fn read_input_register(&mut self, address: u16) -> Result<u16, Exception> {
+        self.input_registers
+            .get(address as usize)
+            .copied()
+            .ok_or(Exception::IllegalDataAddress)
+    }
+}
+
+/// Address of the `index`th object of a request starting at `start`
+fn offset_address(start: u16, index: u16) -> Result<u16, Exception> {
+    start
+        .checked_add(index)
+        .ok_or(Exception::IllegalDataAddress)
+}
+
+/// Executes a request PDU against `map`, writing the response PDU. Returns its length
+fn execute<M: RegisterMap>(map: &mut M, request: &[u8], response: &mut [u8]) -> Result<usize, Exception> {
+    let function = request[0];
+    response[0] = function;
+
+    match function {
+        READ_COILS | READ_DISCRETE_INPUTS => {
+            if request.len() != 5 {
+                return Err(Exception::IllegalDataValue);
+            }
+            let start = get_u16(request, 1);
+            let count = get_u16(request, 3);
+            if count == 0 || count > MAX_READ_BITS {
+                return Err(Exception::IllegalDataValue);
+            }
+
+            let bytes = (count as usize).div_ceil(8);
+            response[1] = bytes as u8;
+            response[2..2 + bytes].fill(0);
+
+            for i in 0..count {
+                let address = offset_address(start, i)?;
+                let value = if function == READ_COILS {
+                    map.read_coil(address)?
+                } else {
+                    map.read_discrete_input(address)?
+                };
+                if value {
+                    response[2 + i as usize / 8] |= 1 << (i % 8);
+                }
+            }
+            Ok(2 + bytes)
+        }
+        READ_HOLDING_REGISTERS | READ_INPUT_REGISTERS => {
+            if request.len() != 5 {
+                return Err(Exception::IllegalDataValue);
+            }
+            let start = get_u16(request, 1);
+            let count = get_u16(request, 3);
+            if count == 0 || count > MAX_READ_REGISTERS {
+                return Err(Exception::IllegalDataValue);
+            }
+
+            response[1] = (count * 2) as u8;
+            for i in 0..count {
+                let address = offset_address(start, i)?;
+                let value = if function == READ_HOLDING_REGISTERS {
+                    map.read_holding_register(address)?
+                } else {
+                    map.read_input_register(address)?
+                };
+                put_u16(response, 2 + 2 * i as usize, value);
+            }
+            Ok(2 + 2 * count as usize)
+        }
+        WRITE_SINGLE_COIL => {
+            if request.len() != 5 {
+                return Err(Exception::IllegalDataValue);
+            }
+            let value = match get_u16(request, 3) {
+                COIL_ON => true,
+                COIL_OFF => false,
+                _ => return Err(Exception::IllegalDataValue),
+            };
+
+            map.write_coil(get_u16(request, 1), value)?;
+            response[..5].copy_from_slice(request);
+            Ok(5)
+        }
+        WRITE_SINGLE_REGISTER => {
+            if request.len() != 5 {
+                return Err(Exception::IllegalDataValue);
+            }
+
+            map.write_holding_register(get_u16(request, 1), get_u16(request, 3))?;
+            response[..5].copy_from_slice(request);
+            Ok(5)
+        }
+        WRITE_MULTIPLE_COILS => {
+            if request.len() < 6 {
+                return Err(Exception::IllegalDataValue);
+            }
+            let start = get_u16(request, 1);
+            let count = get_u16(request, 3);
+            let bytes = request[5] as usize;
+            if count == 0
+                || count > MAX_WRITE_BITS
+                || bytes != (count as usize).div_ceil(8)
+                || request.len() != 6 + bytes
+            {
+                return Err(Exception::IllegalDataValue);
+            }
+
+            for i in 0..count {
+                let value = request[6 + i as usize / 8] & (1 << (i % 8)) != 0;
+                map.write_coil(offset_address(start, i)?, value)?;
+            }
+            response[..5].copy_from_slice(&request[..5]);
+            Ok(5)
+        }
+        WRITE_MULTIPLE_REGISTERS => {
+            if request.len() < 6 {
+                return Err(Exception::IllegalDataValue);
+            }
+            let start = get_u16(request, 1);
+            let count = get_u16(request, 3);
+            let bytes = request[5] as usize;
+            if count == 0
+                || count > MAX_WRITE_REGISTERS
+                || bytes != 2 * count as usize
+                || request.len() != 6 + bytes
+            {
+                return Err(Exception::IllegalDataValue);
+            }
+
+            for i in 0..count {
+                let value = get_u16(request, 6 + 2 * i as usize);
+                map.write_holding_register(offset_address(start, i)?, value)?;
+            }
+            response[..5].copy_from_slice(&request[..5]);
+            Ok(5)
+        }
+        _ => Err(Exception::IllegalFunction),
+    }
+}
+
+pub struct Slave<T, M> {
+    transport: T,
+    map: M,
+    address: u8,
+    rx: [u8; MAX_ADU],
+    rx_len: usize,
+    overflow: bool,
+}
+
+impl<T: Transport, M: RegisterMap> Slave<T, M> {
+    pub fn new(transport: T, address: u8, map: M) -> Self {
+        Slave {
+            transport,
+            map,
+            address,
+            rx: [0; MAX_ADU],
+            rx_len: 0,
+            overflow: false,
+        }
+    }
+
+    /// Releases the transport and register map
+    pub fn free(self) -> (T, M) {
+        (self.transport, self.map)
+    }
+
+    pub fn map(&self) -> &M {
+        &self.map
+    }
+
+    pub fn map_mut(&mut self) -> &mut M {
+        &mut self.map
+    }
+
+    /// Collects received bytes and answers a request once its frame is complete. Call
+    /// from the UART interrupt or often enough to keep the RX FIFO from overflowing.
+    ///
+    /// Returns true if a request addressed to this slave (or broadcast) was executed.
+    /// Frames with a bad CRC are dropped silently, as required by the spec.
+    pub fn poll(&mut self) -> Result<bool, ModbusError> {
+        loop {
+            match self.transport.read() {
+                Ok(byte) => {
+                    if self.rx_len < MAX_ADU {
+                        self.rx[self.rx_len] = byte;
+                        self.rx_len += 1;
+                    } else {
+                        self.overflow = true;
+                    }
+                }
+                Err(nb::Error::WouldBlock) => break,
+                Err(nb::Error::Other(e)) => {
+                    self.rx_len = 0;
+                    self.overflow = false;
+                    return Err(ModbusError::Uart(e));
+                }
+            }
+        }
+
+        // Always check for idle so a stale end-of-frame isn't applied to the next frame
+        let idle = self.transport.frame_idle();
+        if !idle || self.rx_len == 0 {
+            return Ok(false);
+        }
+
+        let len = self.rx_len;
+        self.rx_len = 0;
+        if core::mem::replace(&mut self.overflow, false) || !crc_valid(&self.rx[..len]) {
+            return Ok(false);
+        }
+
+        let address = self.rx[0];
+        if address != self.address && address != BROADCAST {
+            return Ok(false);
+        }
+
+        let mut tx = [0; MAX_ADU];
+        tx[0] = self.address;
+        let pdu_len = match execute(&mut self.map, &self.rx[1..len - 2], &mut tx[1..MAX_ADU - 2]) {
+            Ok(pdu_len) => pdu_len,
+            Err(exception) => {
+                tx[1] = self.rx[1] | EXCEPTION_FLAG;
+                tx[2] = exception as u8;
+                2
+            }
+        };
+
+        // Broadcast requests are executed but never answered
+        if address != BROADCAST {
+            let tx_len = append_crc(&mut tx, 1 + pdu_len);
+            self.transport
+                .write(&tx[..tx_len])
+                .map_err(ModbusError::Uart)?;
+        }
+
+        Ok(true)
+    }
+}
+
+pub struct Master<T, D> {
+    transport: T,
+    delay: D,
+    timeout_us: u32,
+    rx: [u8; MAX_ADU],
+}
+
+impl<T: Transport, D: DelayUs<u32>> Master<T, D> {
+    pub fn new(transport: T, delay: D) -> Self {
+        Master {
+            transport,
+            delay,
+            timeout_us: DEFAULT_TIMEOUT_US,
+            rx: [0; MAX_ADU],
+        }
+    }
+
+    /// Releases the transport and delay
+    pub fn free(self) -> (T, D) {
+        (self.transport, self.delay)
+    }
+
+    /// Sets how long to wait for a response
+    pub fn set_timeout(&mut self, timeout_us: u32) -> &mut Self {
+        self.timeout_us = timeout_us;
+        self
+    }
+
+    /// Sends a request PDU and waits for the response, returns the length of the response
+    /// PDU stored at `self.rx[1..]`. Broadcasts return 0 without waiting.
+    fn transaction(&mut self, slave: u8, request: &[u8]) -> Result<usize, ModbusError> {
+        let mut tx = [0; MAX_ADU];
+        tx[0] = slave;
+        tx[1..1 + request.len()].copy_from_slice(request);
+        let tx_len = append_crc(&mut tx, 1 + request.len());
+
+        // Drop anything left over from a previous exchange
+        self.transport.discard();
+
+        self.transport.write(&tx[..tx_len]).map_err(ModbusError::Uart)?;
+        if slave == BROADCAST {
+            return Ok(0);
+        }
+
+        let mut len = 0;
+        let mut waited_us = 0;
+        loop {
+            loop {
+                match self.transport.read() {
+                    Ok(byte) => {
+                        if len < MAX_ADU {
+                            self.rx[len] = byte;
+                            len += 1;
+                        }
+                    }
+                    Err(nb::Error::WouldBlock) => break,
+                    Err(nb::Error::Other(e)) => return Err(ModbusError::Uart(e)),
+                }
+            }
+
+            if self.transport.frame_idle() && len > 0 {
+                break;
+            }
+
+            if waited_us >= self.timeout_us {
+                return Err(ModbusError::Timeout);
+            }
+            self.delay.delay_us(POLL_INTERVAL_US);
+            waited_us += POLL_INTERVAL_US;
+        }
+
+        if !crc_valid(&self.rx[..len]) {
+            return Err(ModbusError::Crc);
+        }
+        if self.rx[0] != slave || self.rx[1] & !EXCEPTION_FLAG != request[0] {
+            return Err(ModbusError::InvalidResponse);
+        }
+        if self.rx[1] & EXCEPTION_FLAG != 0 {
+            return Err(ModbusError::Exception(Exception::from_code(self.rx[2])));
+        }
+
+        Ok(len - 3)
+    }
+
+    fn read_bits(&mut self, function: u8, slave: u8, address: u16, values: &mut [bool]) -> Result<(), ModbusError> {
+        let count = values.len();
+        if count == 0 || count > MAX_READ_BITS as usize {
+            return Err(ModbusError::InvalidRequest);
+        }
+
+        let mut request = [function, 0, 0, 0, 0];
+        put_u16(&mut request, 1, address);
+        put_u16(&mut request, 3, count as u16);
+
+        let len = self.transaction(slave, &request)?;
+        let bytes = count.div_ceil(8);
+        let pdu = &self.rx[1..1 + len];
+        if len != 2 + bytes || pdu[1] as usize != bytes {
+            return Err(ModbusError::InvalidResponse);
+        }
+
+        for (i, value) in values.iter_mut().enumerate() {
+            *value = pdu[2 + i / 8] & (1 << (i % 8)) != 0;
+        }
+        Ok(())
+    }
+
+    fn read_registers(&mut self, function: u8, slave: u8, address: u16, values: &mut [u16]) -> Result<(), ModbusError> {
+        let count = values.len();
+        if count == 0 || count > MAX_READ_REGISTERS as usize {
+            return Err(ModbusError::InvalidRequest);
+        }
+
+        let mut request = [function, 0, 0, 0, 0];
+        put_u16(&mut request, 1, address);
+        put_u16(&mut request, 3, count as u16);
+
+        let len = self.transaction(slave, &request)?;
+        let pdu = &self.rx[1..1 + len];
+        if len != 2 + 2 * count || pdu[1] as usize != 2 * count {
+            return Err(ModbusError::InvalidResponse);
+        }
+
+        for (i, value) in values.iter_mut().enumerate() {
+            *value = get_u16(pdu, 2 + 2 * i);
+        }
+        Ok(())
+    }
+
+    /// Sends a write request and checks the slave's acknowledgement, which echoes the
+    /// first five bytes of the request
+    fn write(&mut self, slave: u8, request: &[u8]) -> Result<(), ModbusError> {
+        let len = self.transaction(slave, request)?;
+        if slave != BROADCAST && (len != 5 || self.rx[1..6] != request[..5]) {
+            return Err(ModbusError::InvalidResponse);
+        }
+        Ok(())
+    }
+
+    pub fn read_coils(&mut self, slave: u8, address: u16, values: &mut [bool]) -> Result<(), ModbusError> {
+        self.read_bits(READ_COILS, slave, address, values)
+    }
+
+    pub fn read_discrete_inputs(&mut self, slave: u8, address: u16, values: &mut [bool]) -> Result<(), ModbusError> {
+        self.read_bits(READ_DISCRETE_INPUTS, slave, address, values)
+    }
+
+    pub fn read_holding_registers(&mut self, slave: u8, address: u16, values: &mut [u16]) -> Result<(), ModbusError> {
+        self.read_registers(READ_HOLDING_REGISTERS, slave, address, values)
+    }
+
+    pub fn read_input_registers(&mut self, slave: u8, address: u16, values: &mut [u16]) -> Result<(), ModbusError> {
+        self.read_registers(READ_INPUT_REGISTERS, slave, address, values)
+    }
+
+    pub fn write_single_coil(&mut self, slave: u8, address: u16, value: bool) -> Result<(), ModbusError> {
+        let mut request = [WRITE_SINGLE_COIL, 0, 0, 0, 0];
+        put_u16(&mut request, 1, address);
+        put_u16(&mut request, 3, if value { COIL_ON } else { COIL_OFF });
+        self.write(slave, &request)
+    }
+
+    pub fn write_single_register(&mut self, slave: u8, address: u16, value: u16) -> Result<(), ModbusError> {
+        let mut request = [WRITE_SINGLE_REGISTER, 0, 0, 0, 0];
+        put_u16(&mut request, 1, address);
+        put_u16(&mut request, 3, value);
+        self.write(slave, &request)
+    }
+
+    pub fn write_multiple_coils(&mut self, slave: u8, address: u16, values: &[bool]) -> Result<(), ModbusError> {
+        let count = values.len();
+        if count == 0 || count > MAX_WRITE_BITS as usize {
+            return Err(ModbusError::InvalidRequest);
+        }
+
+        let bytes = count.div_ceil(8);
+        let mut request = [0; MAX_ADU - 3];
+        request[0] = WRITE_MULTIPLE_COILS;
+        put_u16(&mut request, 1, address);
+        put_u16(&mut request, 3, count as u16);
+        request[5] = bytes as u8;
+        for (i, value) in values.iter().enumerate() {
+            if *value {
+                request[6 + i / 8] |= 1 << (i % 8);
+            }
+        }
+
+        self.write(slave, &request[..6 + bytes])
+    }
+
+    pub fn write_multiple_registers(&mut self, slave: u8, address: u16, values: &[u16]) -> Result<(), ModbusError> {
+        let count = values.len();
+        if count == 0 || count > MAX_WRITE_REGISTERS as usize {
+            return Err(ModbusError::InvalidRequest);
+        }
+
+        let mut request = [0; MAX_ADU - 3];
+        request[0] = WRITE_MULTIPLE_REGISTERS;
+        put_u16(&mut request, 1, address);
+        put_u16(&mut request, 3, count as u16);
+        request[5] = (2 * count) as u8;
+        for (i, value) in values.iter().enumerate() {
+            put_u16(&mut request, 6 + 2 * i, *value);
+        }
+
+        self.write(slave, &request[..6 + 2 * count])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    type Queue = Rc<RefCell<VecDeque<u8>>>;
+
+    /// One end of an in-memory link, frames written to one end arrive at the other whole
+    struct MemoryTransport {
+        rx: Queue,
+        tx: Queue,
+        received: bool,
+    }
+
+    impl MemoryTransport {
+        fn pair() -> (Self, Self) {
+            let a = Queue::default();
+            let b = Queue::default();
+            (
+                MemoryTransport { rx: a.clone(), tx: b.clone(), received: false },
+                MemoryTransport { rx: b, tx: a, received: false },
+            )
+        }
+
+        /// Sends `frame` to the other end from outside the code under test
+        fn send(&self, frame: &[u8]) {
+            self.tx.borrow_mut().extend(frame);
+        }
+
+        /// Takes everything the other end has sent
+        fn received(&self) -> Vec<u8> {
+            self.rx.borrow_mut().drain(..).collect()
+        }
+    }
+
+    impl Transport for MemoryTransport {
+        fn read(&mut self) -> nb::Result<u8, UartError> {
+            let byte = self.rx.borrow_mut().pop_front().ok_or(nb::Error::WouldBlock)?;
+            self.received = true;
+            Ok(byte)
+        }
+
+        fn frame_idle(&mut self) -> bool {
+            let idle = self.received && self.rx.borrow().is_empty();
+            if idle {
+                self.received = false;
+            }
+            idle
+        }
+
+        fn write(&mut self, frame: &[u8]) -> Result<(), UartError> {
+            self.tx.borrow_mut().extend(frame);
+            Ok(())
+        }
+
+        fn discard(&mut self) {
+            self.rx.borrow_mut().clear();
+            self.received = false;
+        }
+    }
+
+    type TestRegisters = Registers<16, 16, 16, 16>;
+
+    /// Runs the slave while the master waits for its response
+    struct SlaveDelay(Slave<MemoryTransport, TestRegisters>);
+
+    impl DelayUs<u32> for SlaveDelay {
+        fn delay_us(&mut self, _us: u32) {
+            self.0.poll().unwrap();
+        }
+    }
+
+    fn with_crc(frame: &[u8]) -> Vec<u8> {
+        let mut buffer = [0; MAX_ADU];
+        buffer[..frame.len()].copy_from_slice(frame);
+        let len = append_crc(&mut buffer, frame.len());
+        buffer[..len].to_vec()
+    }
+
+    fn slave() -> (Slave<MemoryTransport, TestRegisters>, MemoryTransport) {
+        let (local, remote) = MemoryTransport::pair();
+        (Slave::new(local, 0x11, TestRegisters::new()), remote)
+    }
+
+    fn master() -> Master<MemoryTransport, SlaveDelay> {
+        let (local, remote) = MemoryTransport::pair();
+        Master::new(local, SlaveDelay(Slave::new(remote, 0x11, TestRegisters::new())))
+    }
+
+    #[test]
+    fn crc_known_answers() {
+        assert_eq!(crc16(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x0A]), 0xCDC5);
+        assert_eq!(crc16(b"123456789"), 0x4B37);
+        assert_eq!(with_crc(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x0A])[6..], [0xC5, 0xCD]);
+    }
+
+    #[test]
+    fn slave_reads_holding_registers() {
+        let (mut slave, remote) = slave();
+        slave.map_mut().holding_registers[1] = 0x1234;
+        slave.map_mut().holding_registers[2] = 0xABCD;
+
+        remote.send(&with_crc(&[0x11, 0x03, 0x00, 0x01, 0x00, 0x02]));
+        assert!(slave.poll().unwrap());
+        assert_eq!(remote.received(), with_crc(&[0x11, 0x03, 0x04, 0x12, 0x34, 0xAB, 0xCD]));
+    }
+
+    #[test]
+    fn slave_answers_illegal_address_with_exception() {
+        let (mut slave, remote) = slave();
+
+        remote.send(&with_crc(&[0x11, 0x03, 0x00, 0x0F, 0x00, 0x02]));
+        assert!(slave.poll().unwrap());
+        assert_eq!(remote.received(), with_crc(&[0x11, 0x83, 0x02]));
+    }
+
+    #[test]
+    fn slave_ignores_bad_crc_and_other_addresses() {
+        let (mut slave, remote) = slave();
+
+        let mut frame = with_crc(&[0x11, 0x06, 0x00, 0x01, 0x00, 0x05]);
+        frame[6] ^= 0xFF;
+        remote.send(&frame);
+        assert!(!slave.poll().unwrap());
+
+        remote.send(&with_crc(&[0x12, 0x06, 0x00, 0x01, 0x00, 0x05]));
+        assert!(!slave.poll().unwrap());
+
+        assert!(remote.received().is_empty());
+        assert_eq!(slave.map().holding_registers[1], 0);
+    }
+
+    #[test]
+    fn slave_executes_broadcast_without_answering() {
+        let (mut slave, remote) = slave();
+
+        remote.send(&with_crc(&[0x00, 0x06, 0x00, 0x01, 0x00, 0x05]));
+        assert!(slave.poll().unwrap());
+        assert!(remote.received().is_empty());
+        assert_eq!(slave.map().holding_registers[1], 5);
+    }
+
+    #[test]
+    fn master_round_trips_registers() {
+        let mut master = master();
+
+        master.write_multiple_registers(0x11, 3, &[1, 2, 0xFFFF]).unwrap();
+        master.write_single_register(0x11, 7, 0x55AA).unwrap();
+
+        let mut values = [0; 6];
+        master.read_holding_registers(0x11, 2, &mut values).unwrap();
+        assert_eq!(values, [0, 1, 2, 0xFFFF, 0, 0x55AA]);
+    }
+
+    #[test]
+    fn master_round_trips_coils() {
+        let mut master = master();
+
+        master.write_multiple_coils(0x11, 0, &[true, false, true, true, false, false, false, false, true]).unwrap();
+        master.write_single_coil(0x11, 1, true).unwrap();
+
+        let mut values = [false; 10];
+        master.read_coils(0x11, 0, &mut values).unwrap();
+        assert_eq!(values, [true, true, true, true, false, false, false, false, true, false]);
+    }
+
+    #[test]
+    fn master_reports_exceptions() {
+        let mut master = master();
+
+        let mut values = [0; 2];
+        let result = master.read_holding_registers(0x11, 15, &mut values);
+        assert!(matches!(result, Err(ModbusError::Exception(Exception::IllegalDataAddress))));
+    }
+
+    #[test]
+    fn master_discards_stale_input() {
+        let mut master = master();
+        master.transport.rx.borrow_mut().extend([0x11, 0x03]);
+        master.transport.received = true;
+
+        master.write_single_register(0x11, 0, 7).unwrap();
+        assert_eq!(master.delay.0.map().holding_registers[0], 7);
+    }
+
+    #[test]
+    fn master_times_out_without_answer() {
+        let mut master = master();
+        master.set_timeout(1_000);
+
+        let mut values = [0; 1];
+        let result = master.read_holding_registers(0x12, 0, &mut values);
+        assert!(matches!(result, Err(ModbusError::Timeout)));
+    }
+}
@@ -0,0 +1,89 @@
+//! RS-485 half-duplex operation of `Uart0`/`Uart1` with automatic driver-enable.
+//!
+//! Any `OutputPin` can drive the transceiver's DE input, including `gpio::OutputPin`.
+//! To use the UART's RTS pin for DE, configure it as a GPIO output instead of its UART
+//! alternate function and leave hardware flow control disabled.
+
+use embedded_hal::blocking::delay::DelayUs;
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal::serial::{Read, Write};
+
+use crate::uart::UartError;
+
+#[derive(Debug)]
+pub enum Rs485Error<E> {
+    Uart(UartError),
+    /// The driver-enable pin could not be driven
+    DriverEnable(E),
+}
+
+pub struct Rs485<S, DE, D> {
+    uart: S,
+    de: DE,
+    delay: D,
+    pre_delay_us: u32,
+    post_delay_us: u32,
+}
+
+impl<S, DE, D> Rs485<S, DE, D>
+where
+    S: Read<u8, Error = UartError> + Write<u8, Error = UartError>,
+    DE: OutputPin,
+    D: DelayUs<u32>,
+{
+    /// Creates the wrapper and puts the transceiver in receive mode
+    pub fn new(uart: S, mut de: DE, delay: D) -> Result<Self, Rs485Error<DE::Error>> {
+        de.set_low().map_err(Rs485Error::DriverEnable)?;
+
+        Ok(Rs485 {
+            uart,
+            de,
+            delay,
+            pre_delay_us: 0,
+            post_delay_us: 0,
+        })
+    }
+
+    /// Releases the UART, driver-enable pin and delay
+    pub fn free(self) -> (S, DE, D) {
+        (self.uart, self.de, self.delay)
+    }
+
+    /// Sets the time DE is held before the first and after the last transmitted bit
+    pub fn set_turnaround(&mut self, pre_delay_us: u32, post_delay_us: u32) -> &mut Self {
+        self.pre_delay_us = pre_delay_us;
+        self.post_delay_us = post_delay_us;
+        self
+    }
+
+    /// Enables the driver, transmits `bytes` and switches back to receive once the
+    /// shift register has drained
+    pub fn write_blocking(&mut self, bytes: &[u8]) -> Result<(), Rs485Error<DE::Error>> {
+        self.de.set_high().map_err(Rs485Error::DriverEnable)?;
+        if self.pre_delay_us > 0 {
+            self.delay.delay_us(self.pre_delay_us);
+        }
+
+        let result = self.transmit(bytes);
+
+        if self.post_delay_us > 0 {
+            self.delay.delay_us(self.post_delay_us);
+        }
+        // Always release the bus, even if the transmit failed
+        self.de.set_low().map_err(Rs485Error::DriverEnable)?;
+
+        result.map_err(Rs485Error::Uart)
+    }
+
+    fn transmit(&mut self, bytes: &[u8]) -> Result<(), UartError> {
+        for byte in bytes {
+            nb::block!(self.uart.write(*byte))?;
+        }
+        nb::block!(self.uart.flush())
+    }
+
+    /// Reads a received byte, the transceiver is in receive mode outside `write_blocking`
+    pub fn read(&mut self) -> nb::Result<u8, UartError> {
+        self.uart.read()
+    }
+}
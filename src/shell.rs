@@ -0,0 +1,507 @@
+//! Interactive line-based console for bring-up, running on `Uart0`/`Uart1` without heap.
+//!
+//! Lines are edited in place with backspace, Ctrl-C discards the line and the up/down
+//! arrow keys walk through the last few commands. Each line is split on whitespace and
+//! looked up first among the built-in commands, then in the application's static table:
+//!
+//! ```ignore
+//! fn led(board: &mut Board, args: &[&str], out: &mut dyn fmt::Write) -> Result<(), CommandError> {
+//!     match args.get(1) {
+//!         Some(&"on") => board.led.pin_high(),
+//!         Some(&"off") => board.led.pin_low(),
+//!         _ => return Err(CommandError::Usage),
+//!     }
+//!     writeln!(out, "ok")?;
+//!     Ok(())
+//! }
+//!
+//! static COMMANDS: [Command<Board>; 1] = [Command {
+//!     name: "led",
+//!     usage: "on|off",
+//!     help: "switch the LED",
+//!     handler: led,
+//! }];
+//!
+//! let mut shell = Shell::new(uart, &COMMANDS);
+//! shell.prompt();
+//! loop {
+//!     shell.poll(&mut board);
+//! }
+//! ```
+//!
+//! Built-in commands:
+//!
+//! * `help` lists all commands
+//! * `peek <addr> [count]` reads 32-bit words
+//! * `poke <addr> <value>` writes a 32-bit word
+//! * `gpio [pin]` shows input levels read through `Gpio0`
+//! * `i2cscan <0|1>` lists the 7-bit addresses acknowledging on an I2C bus
+
+use core::fmt::{self, Write as _};
+
+use embedded_hal::blocking::delay::DelayUs;
+use embedded_hal::serial::{Read, Write};
+
+use crate::gpio::Gpio0;
+use crate::i2c::{I2c0, I2c1};
+use crate::uart::UartError;
+
+/// Longest line that can be entered, further characters are dropped
+pub const LINE_LEN: usize = 80;
+
+/// Number of previous lines kept for the arrow keys
+pub const HISTORY_LEN: usize = 4;
+
+/// Most words a line is split into, including the command name
+pub const MAX_ARGS: usize = 8;
+
+const PROMPT: &str = "> ";
+
+/// Words peek prints per line
+const PEEK_WORDS_PER_LINE: u32 = 4;
+
+/// Valid 7-bit addresses, the others are reserved
+const I2C_SCAN_FIRST: u8 = 0x08;
+const I2C_SCAN_LAST: u8 = 0x77;
+
+const GPIO_PINS: u8 = 14;
+
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7F;
+const CTRL_C: u8 = 0x03;
+const ESCAPE: u8 = 0x1B;
+
+#[derive(Debug)]
+pub enum CommandError {
+    /// The arguments don't match the command, the shell prints its usage
+    Usage,
+    /// An argument couldn't be parsed or is out of range
+    InvalidArgument,
+    /// The peripheral the command needs isn't available in the context or is busy
+    Unavailable,
+    /// Writing the output failed
+    Output,
+}
+
+impl From<fmt::Error> for CommandError {
+    fn from(_: fmt::Error) -> Self {
+        CommandError::Output
+    }
+}
+
+/// Runs a command. `args[0]` is the command name
+pub type Handler<T> = fn(&mut T, &[&str], &mut dyn fmt::Write) -> Result<(), CommandError>;
+
+/// An entry of the application's command table
+pub struct Command<T> {
+    pub name: &'static str,
+    /// Arguments, printed by `help` and after a usage error
+    pub usage: &'static str,
+    pub help: &'static str,
+    pub handler: Handler<T>,
+}
+
+/// Peripherals the built-in commands work on. Each defaults to unavailable, `()` can be
+/// used as the context if there are none.
+pub trait Context {
+    fn gpio(&mut self) -> Option<&Gpio0> {
+        None
+    }
+
+    /// The bus and a delay bounding the waits of `i2cscan`
+    fn i2c0(&mut self) -> Option<(&mut I2c0, &mut dyn DelayUs<u32>)> {
+        None
+    }
+
+    fn i2c1(&mut self) -> Option<(&mut I2c1, &mut dyn DelayUs<u32>)> {
+        None
+    }
+}
+
+impl Context for () {}
+
+/// Parses a decimal or `0x` prefixed hexadecimal number
+pub fn parse_u32(arg: &str) -> Result<u32, CommandError> {
+    let parsed = match arg.strip_prefix("0x").or_else(|| arg.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => arg.parse(),
+    };
+    parsed.map_err(|_| CommandError::InvalidArgument)
+}
+
+/// `fmt::Write` adapter for the UART, translating `\n` to `\r\n`
+pub struct Console<'a, S> {
+    uart: &'a mut S,
+}
+
+impl<'a, S> fmt::Write for Console<'a, S>
+where
+    S: Write<u8, Error = UartError>,
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                nb::block!(self.uart.write(b'\r')).map_err(|_| fmt::Error)?;
+            }
+            nb::block!(self.uart.write(byte)).map_err(|_| fmt::Error)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Line {
+    bytes: [u8; LINE_LEN],
+    len: usize,
+}
+
+impl Line {
+    const EMPTY: Line = Line {
+        bytes: [0; LINE_LEN],
+        len: 0,
+    };
+
+    fn as_str(&self) -> &str {
+        // Only printable ASCII is ever stored
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
+/// Position in an arrow key escape sequence, `ESC [ A` is up and `ESC [ B` is down
+#[derive(Copy, Clone, PartialEq)]
+enum Escape {
+    None,
+    Started,
+    Csi,
+}
+
+pub struct Shell<'a, S, T> {
+    uart: S,
+    commands: &'a [Command<T>],
+    line: Line,
+    history: [Line; HISTORY_LEN],
+    /// Number of valid history entries, the newest is at index 0
+    history_len: usize,
+    /// History entry shown in the line, 0 when editing a new line
+    browsing: usize,
+    escape: Escape,
+    last_was_cr: bool,
+}
+
+impl<'a, S, T> Shell<'a, S, T>
+where
+    S: Read<u8, Error = UartError> + Write<u8, Error = UartError>,
+    T: Context,
+{
+    pub fn new(uart: S, commands: &'a [Command<T>]) -> Self {
+        Shell {
+            uart,
+            commands,
+            line: Line::EMPTY,
+            history: [Line::EMPTY; HISTORY_LEN],
+            history_len: 0,
+            browsing: 0,
+            escape: Escape::None,
+            last_was_cr: false,
+        }
+    }
+
+    /// Releases the UART
+    pub fn free(self) -> S {
+        self.uart
+    }
+
+    fn console(&mut self) -> Console<'_, S> {
+        Console {
+            uart: &mut self.uart,
+        }
+    }
+
+    /// Prints the prompt, call once at start up
+    pub fn prompt(&mut self) {
+        let _ = self.console().write_str(PROMPT);
+    }
+
+    /// Handles every received character, running the line once it's complete. Call from
+    /// the main loop or the UART interrupt with the context the commands work on.
+    pub fn poll(&mut self, context: &mut T) {
+        loop {
+            match self.uart.read() {
+                Ok(byte) => self.receive(byte, context),
+                Err(nb::Error::WouldBlock) => break,
+                // Line noise or a break, nothing a console can do about it
+                Err(nb::Error::Other(_)) => continue,
+            }
+        }
+    }
+
+    fn receive(&mut self, byte: u8, context: &mut T) {
+        let after_cr = self.last_was_cr;
+        self.last_was_cr = byte == b'\r';
+
+        match (self.escape, byte) {
+            (Escape::Started, b'[') => {
+                self.escape = Escape::Csi;
+                return;
+            }
+            (Escape::Csi, b'A') => {
+                self.escape = Escape::None;
+                self.recall(self.browsing + 1);
+                return;
+            }
+            (Escape::Csi, b'B') => {
+                self.escape = Escape::None;
+                self.recall(self.browsing.saturating_sub(1));
+                return;
+            }
+            (Escape::Started | Escape::Csi, _) => {
+                // Other keys are ignored, their sequence ends with a letter or `~`
+                if byte.is_ascii_alphabetic() || byte == b'~' || self.escape == Escape::Started {
+                    self.escape = Escape::None;
+                }
+                return;
+            }
+            (Escape::None, _) => {}
+        }
+
+        match byte {
+            ESCAPE => self.escape = Escape::Started,
+            b'\n' if after_cr => {}
+            b'\r' | b'\n' => {
+                let _ = self.console().write_str("\n");
+                self.execute(context);
+                let _ = self.console().write_str(PROMPT);
+            }
+            BACKSPACE | DELETE if self.line.len > 0 => {
+                self.line.len -= 1;
+                let _ = self.console().write_str("\x08 \x08");
+            }
+            CTRL_C => {
+                self.line.len = 0;
+                self.browsing = 0;
+                let _ = self.console().write_str("^C\n");
+                let _ = self.console().write_str(PROMPT);
+            }
+            b' '..=b'~' if self.line.len < LINE_LEN => {
+                self.line.bytes[self.line.len] = byte;
+                self.line.len += 1;
+                let _ = nb::block!(self.uart.write(byte));
+            }
+            _ => {}
+        }
+    }
+
+    /// Replaces the line with history entry `index`, 0 clears it
+    fn recall(&mut self, index: usize) {
+        let index = index.min(self.history_len);
+        if index == self.browsing {
+            return;
+        }
+
+        let mut console = Console {
+            uart: &mut self.uart,
+        };
+        for _ in 0..self.line.len {
+            let _ = console.write_str("\x08 \x08");
+        }
+
+        self.browsing = index;
+        self.line = if index == 0 {
+            Line::EMPTY
+        } else {
+            self.history[index - 1]
+        };
+        let _ = console.write_str(self.line.as_str());
+    }
+
+    fn remember(&mut self) {
+        let newest = &self.history[0];
+        if self.history_len > 0 && newest.as_str() == self.line.as_str() {
+            return;
+        }
+
+        self.history.copy_within(..HISTORY_LEN - 1, 1);
+        self.history[0] = self.line;
+        self.history_len = (self.history_len + 1).min(HISTORY_LEN);
+    }
+
+    fn execute(&mut self, context: &mut T) {
+        self.browsing = 0;
+        if self.line.as_str().trim().is_empty() {
+            self.line.len = 0;
+            return;
+        }
+        self.remember();
+
+        let line = self.line;
+        self.line.len = 0;
+
+        let mut args = [""; MAX_ARGS];
+        let mut count = 0;
+        let mut out = Console {
+            uart: &mut self.uart,
+        };
+
+        for word in line.as_str().split_whitespace() {
+            if count == MAX_ARGS {
+                let _ = writeln!(out, "too many arguments");
+                return;
+            }
+            args[count] = word;
+            count += 1;
+        }
+        let args = &args[..count];
+
+        if args[0] == "help" {
+            let commands = builtins::<T>();
+            for command in commands.iter().chain(self.commands) {
+                let _ = writeln!(out, "  {} {} - {}", command.name, command.usage, command.help);
+            }
+            return;
+        }
+
+        let builtin = builtins::<T>().into_iter().find(|command| command.name == args[0]);
+        let (usage, result) = match builtin {
+            Some(command) => (command.usage, (command.handler)(context, args, &mut out)),
+            None => match self.commands.iter().find(|command| command.name == args[0]) {
+                Some(command) => (command.usage, (command.handler)(context, args, &mut out)),
+                None => {
+                    let _ = writeln!(out, "unknown command '{}', try 'help'", args[0]);
+                    return;
+                }
+            },
+        };
+
+        let _ = match result {
+            Ok(()) => Ok(()),
+            Err(CommandError::Usage) => writeln!(out, "usage: {} {}", args[0], usage),
+            Err(CommandError::InvalidArgument) => writeln!(out, "invalid argument"),
+            Err(CommandError::Unavailable) => writeln!(out, "not available"),
+            Err(CommandError::Output) => Ok(()),
+        };
+    }
+}
+
+fn builtins<T: Context>() -> [Command<T>; 4] {
+    [
+        Command {
+            name: "peek",
+            usage: "<addr> [count]",
+            help: "read 32-bit words",
+            handler: peek,
+        },
+        Command {
+            name: "poke",
+            usage: "<addr> <value>",
+            help: "write a 32-bit word",
+            handler: poke,
+        },
+        Command {
+            name: "gpio",
+            usage: "[pin]",
+            help: "show GPIO input levels",
+            handler: gpio,
+        },
+        Command {
+            name: "i2cscan",
+            usage: "<0|1>",
+            help: "list devices on an I2C bus",
+            handler: i2c_scan,
+        },
+    ]
+}
+
+fn word_address(arg: &str) -> Result<u32, CommandError> {
+    let address = parse_u32(arg)?;
+    if address % 4 != 0 {
+        return Err(CommandError::InvalidArgument);
+    }
+    Ok(address)
+}
+
+fn peek<T: Context>(_: &mut T, args: &[&str], out: &mut dyn fmt::Write) -> Result<(), CommandError> {
+    let address = word_address(args.get(1).ok_or(CommandError::Usage)?)?;
+    let count = match args.get(2) {
+        Some(arg) => parse_u32(arg)?,
+        None => 1,
+    };
+    if args.len() > 3 {
+        return Err(CommandError::Usage);
+    }
+
+    for index in 0..count {
+        let word_address = address.wrapping_add(index * 4);
+        if index % PEEK_WORDS_PER_LINE == 0 {
+            if index > 0 {
+                writeln!(out)?;
+            }
+            write!(out, "{:08x}:", word_address)?;
+        }
+        let value = unsafe { core::ptr::read_volatile(word_address as *const u32) };
+        write!(out, " {:08x}", value)?;
+    }
+    if count > 0 {
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn poke<T: Context>(_: &mut T, args: &[&str], _: &mut dyn fmt::Write) -> Result<(), CommandError> {
+    if args.len() != 3 {
+        return Err(CommandError::Usage);
+    }
+    let address = word_address(args[1])?;
+    let value = parse_u32(args[2])?;
+
+    unsafe { core::ptr::write_volatile(address as *mut u32, value) };
+    Ok(())
+}
+
+fn gpio<T: Context>(context: &mut T, args: &[&str], out: &mut dyn fmt::Write) -> Result<(), CommandError> {
+    let levels = context.gpio().ok_or(CommandError::Unavailable)?.input_levels();
+
+    match args {
+        [_] => {
+            for pin in 0..GPIO_PINS {
+                writeln!(out, "  P0.{:<2} {}", pin, (levels >> pin) & 1)?;
+            }
+        }
+        [_, pin] => {
+            let pin = parse_u32(pin)?;
+            if pin >= GPIO_PINS as u32 {
+                return Err(CommandError::InvalidArgument);
+            }
+            writeln!(out, "P0.{} {}", pin, (levels >> pin) & 1)?;
+        }
+        _ => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+fn i2c_scan<T: Context>(context: &mut T, args: &[&str], out: &mut dyn fmt::Write) -> Result<(), CommandError> {
+    let mut found = [false; 128];
+
+    match args {
+        [_, "0"] => {
+            let (i2c, delay) = context.i2c0().ok_or(CommandError::Unavailable)?;
+            for address in I2C_SCAN_FIRST..=I2C_SCAN_LAST {
+                found[address as usize] = i2c.probe(address, delay).map_err(|_| CommandError::Unavailable)?;
+            }
+        }
+        [_, "1"] => {
+            let (i2c, delay) = context.i2c1().ok_or(CommandError::Unavailable)?;
+            for address in I2C_SCAN_FIRST..=I2C_SCAN_LAST {
+                found[address as usize] = i2c.probe(address, delay).map_err(|_| CommandError::Unavailable)?;
+            }
+        }
+        _ => return Err(CommandError::Usage),
+    }
+
+    let mut count = 0;
+    for (address, _) in found.iter().enumerate().filter(|(_, found)| **found) {
+        writeln!(out, "  0x{:02x}", address)?;
+        count += 1;
+    }
+    writeln!(out, "{} device(s) found", count)?;
+    Ok(())
+}
@@ -8,6 +8,15 @@ pub enum TimerMode {
     Continuous = 0b001,
 }
 
+/// A free-running counter, used for timestamping (e.g. `uart::autobaud`)
+pub trait Counter {
+    /// Current count, wraps to 0 after `u32::MAX`
+    fn count(&self) -> u32;
+
+    /// Count rate in Hz
+    fn frequency(&self) -> u32;
+}
+
 macro_rules! timer {
     ($tim:ty, $name:ident) => {
         pub struct $name {
@@ -57,6 +66,29 @@ macro_rules! timer {
                 self.set_mode(TimerMode::OneShot);
                 self.timer.cn.modify(|_, w| w.ten().set_bit());
             }
+
+            /// Starts the timer counting at the peripheral clock over the full 32 bit range
+            pub fn start_free_running(&mut self) {
+                self.set_mode(TimerMode::Continuous);
+                unsafe {
+                    self.timer.cmp.write(|w| w.bits(u32::MAX));
+                    self.timer.cnt.write(|w| w.bits(0));
+                }
+                self.timer.cn.modify(|_, w| w.ten().set_bit());
+            }
+        }
+
+        impl Counter for $name {
+            fn count(&self) -> u32 {
+                self.timer.cnt.read().bits()
+            }
+
+            fn frequency(&self) -> u32 {
+                // The count clock is PCLK divided by 2^(PRES3:PRES)
+                let cn = self.timer.cn.read();
+                let prescale = (cn.pres3().bit() as u32) << 3 | cn.pres().bits() as u32;
+                self.peripheral_clk_hz as u32 >> prescale
+            }
         }
     };
 }
@@ -1,5 +1,20 @@
+pub mod autobaud;
+pub mod selftest;
+
+use crate::dma::{Channel, CircularTransfer, DmaPeripheral, Endpoint, Request, Transfer};
+use crate::gpio::{self, Function, Gpio0, InputPin as GpioInputPin, InterruptEdgePolarity, PinId, Resistor, P0_0, P0_1, P0_10, P0_11, P0_12, P0_13, P0_4, P0_5, P0_6, P0_7};
+use crate::timer::Counter;
+use autobaud::AutobaudError;
+use selftest::{Format, RunResult, PATTERN};
+use embedded_hal::blocking::delay::DelayUs;
+use embedded_hal::digital::v2::InputPin;
+use embedded_hal::serial;
+use embedded_io::{ErrorKind, ErrorType, ReadReady, WriteReady};
 use libm::{powf, floorf};
+#[cfg(feature = "async")]
+use core::task::Poll;
 
+#[derive(Copy, Clone)]
 pub enum Parity {
     Even,
     Odd,
@@ -8,11 +23,13 @@ pub enum Parity {
     None
 }
 
+#[derive(Copy, Clone)]
 pub enum ParityLevel {
     Zeros = 0,
     Ones = 1,
 }
 
+#[derive(Copy, Clone)]
 pub enum StopBits {
     _1,
     _1_5,
@@ -24,6 +41,7 @@ pub enum FlowControlPolarity {
     AssertOne,
 }
 
+#[derive(Copy, Clone)]
 pub enum CharSize {
     _5,
     _6,
@@ -55,9 +73,35 @@ pub enum Interrupts {
     LastBreak = 0x200,
 }
 
+/// An item read from the RX stream by `read_event`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RxEvent {
+    Data(u8),
+    Break,
+}
+
 #[derive(Debug)]
 pub enum UartError {
     BaudRateConfiguration,
+    /// A break was received in place of a data byte
+    Break,
+    /// `read_until_idle` needs an RX timeout, see `set_rx_timeout`
+    RxTimeoutDisabled,
+}
+
+impl embedded_io::Error for UartError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            UartError::BaudRateConfiguration | UartError::RxTimeoutDisabled => ErrorKind::InvalidInput,
+            UartError::Break => ErrorKind::Other,
+        }
+    }
+}
+
+/// Break control shared by the UART ports so protocol drivers (e.g. `lin`) can be
+/// generic over `Uart0`/`Uart1`
+pub trait BreakControl {
+    fn set_break(&mut self, enable: bool);
 }
 
 pub struct UartRxFifo {
@@ -65,20 +109,151 @@ pub struct UartRxFifo {
     buffer: [u8; 8],
 }
 
+/// Pins that can carry the TX signal of `UART`
+pub trait TxPin<UART>: PinId {
+    const FUNCTION: Function;
+}
+
+/// Pins that can carry the RX signal of `UART`
+pub trait RxPin<UART>: PinId {
+    const FUNCTION: Function;
+}
+
+/// Pins that can carry the CTS signal of `UART`
+pub trait CtsPin<UART>: PinId {
+    const FUNCTION: Function;
+}
+
+/// Pins that can carry the RTS signal of `UART`
+pub trait RtsPin<UART>: PinId {
+    const FUNCTION: Function;
+}
+
+macro_rules! uart_pins {
+    ($uart:ty, $signal:ident, $($pin:ty => $function:expr),+) => {
+        $(
+            impl $signal<$uart> for $pin {
+                const FUNCTION: Function = $function;
+            }
+        )+
+    };
+}
+
+// Pin mapping from the MAX32660 datasheet pin description
+uart_pins!(max32660_pac::UART0, TxPin, P0_4 => Function::AF2);
+uart_pins!(max32660_pac::UART0, RxPin, P0_5 => Function::AF2);
+uart_pins!(max32660_pac::UART0, CtsPin, P0_6 => Function::AF2);
+uart_pins!(max32660_pac::UART0, RtsPin, P0_7 => Function::AF2);
+uart_pins!(max32660_pac::UART1, TxPin, P0_10 => Function::AF2, P0_4 => Function::AF3, P0_0 => Function::AF3);
+uart_pins!(max32660_pac::UART1, RxPin, P0_11 => Function::AF2, P0_5 => Function::AF3, P0_1 => Function::AF3);
+uart_pins!(max32660_pac::UART1, CtsPin, P0_12 => Function::AF2);
+uart_pins!(max32660_pac::UART1, RtsPin, P0_13 => Function::AF2);
+
+/// UART registers saved by `enable_wake_on_rx` while the RX pin waits for activity as a
+/// GPIO, put back by `restore_after_wake`
+pub struct WakeState {
+    rx: GpioInputPin,
+    ctrl: u32,
+    baud0: u32,
+    baud1: u32,
+    thresh_ctrl: u32,
+    int_en: u32,
+    dma: u32,
+}
+
+/// Tasks waiting in the async `Read`/`Write` impls, woken by `Uart0::on_interrupt`
+#[cfg(feature = "async")]
+struct Wakers {
+    rx: atomic_waker::AtomicWaker,
+    tx: atomic_waker::AtomicWaker,
+}
+
+#[cfg(feature = "async")]
+impl Wakers {
+    const fn new() -> Self {
+        Wakers {
+            rx: atomic_waker::AtomicWaker::new(),
+            tx: atomic_waker::AtomicWaker::new(),
+        }
+    }
+}
+
+/// TX DMA requests are raised while the TX FIFO holds fewer bytes than this
+const TX_DMA_LEVEL: u8 = 4;
+/// RX DMA requests are raised once the RX FIFO holds this many bytes
+const RX_DMA_LEVEL: u8 = 1;
+
 #[macro_export]
 macro_rules! uart {
-    ($UARTX:ty, $name:ident) => {
-        pub struct $name {
+    ($UARTX:ty, $name:ident, $TX:ty, $RX:ty, $tx_request:expr, $rx_request:expr, $clock_disable:ident) => {
+        /// The UART with the pins it was created with, `FC` holds the CTS and RTS pins
+        /// taken by `new_with_flow_control`. The defaults are the AF2 TX and RX pins.
+        pub struct $name<TX = $TX, RX = $RX, FC = ()> {
             uart: $UARTX,
+            tx: TX,
+            rx: RX,
+            flow_control: FC,
         }
 
-        impl $name {
-            pub fn new(uart: $UARTX) -> Self {
+        impl<TX: TxPin<$UARTX>, RX: RxPin<$UARTX>> $name<TX, RX> {
+            /// Takes the TX and RX pins and switches them to their UART alternate function
+            pub fn new(uart: $UARTX, tx: TX, rx: RX) -> Self {
+                Gpio0::set_function(TX::PIN, TX::FUNCTION);
+                Gpio0::set_function(RX::PIN, RX::FUNCTION);
+                gpio::claim(TX::PIN);
+                gpio::claim(RX::PIN);
+
                 $name {
                     uart: uart,
+                    tx,
+                    rx,
+                    flow_control: (),
                 }
             }
 
+            /// Like `new`, also taking the CTS and RTS pins for hardware flow control
+            pub fn new_with_flow_control<CTS, RTS>(uart: $UARTX, tx: TX, rx: RX, cts: CTS, rts: RTS) -> $name<TX, RX, (CTS, RTS)>
+            where
+                CTS: CtsPin<$UARTX>,
+                RTS: RtsPin<$UARTX>,
+            {
+                Gpio0::set_function(CTS::PIN, CTS::FUNCTION);
+                Gpio0::set_function(RTS::PIN, RTS::FUNCTION);
+                gpio::claim(CTS::PIN);
+                gpio::claim(RTS::PIN);
+
+                let uart = Self::new(uart, tx, rx);
+                $name {
+                    uart: uart.uart,
+                    tx: uart.tx,
+                    rx: uart.rx,
+                    flow_control: (cts, rts),
+                }
+            }
+
+            /// Releases the peripheral and the pins taken by `new`
+            pub fn free(self) -> ($UARTX, TX, RX) {
+                gpio::release(TX::PIN);
+                gpio::release(RX::PIN);
+
+                (self.uart, self.tx, self.rx)
+            }
+        }
+
+        impl<TX: TxPin<$UARTX>, RX: RxPin<$UARTX>, CTS: CtsPin<$UARTX>, RTS: RtsPin<$UARTX>> $name<TX, RX, (CTS, RTS)> {
+            /// Releases the peripheral and the pins taken by `new_with_flow_control`
+            pub fn free_with_flow_control(self) -> ($UARTX, TX, RX, CTS, RTS) {
+                gpio::release(TX::PIN);
+                gpio::release(RX::PIN);
+                gpio::release(CTS::PIN);
+                gpio::release(RTS::PIN);
+
+                let (cts, rts) = self.flow_control;
+                (self.uart, self.tx, self.rx, cts, rts)
+            }
+        }
+
+        impl<TX: TxPin<$UARTX>, RX: RxPin<$UARTX>, FC> $name<TX, RX, FC> {
             pub fn enable(&self) {
                 unsafe {
                     self.uart.ctrl.modify(|r, w| {
@@ -92,14 +267,15 @@ macro_rules! uart {
 
                 let mut div: f32 = 0.0;
                 let mut factor = 0;
-                for factor in 0..4 {
-                    div = (peripheral_clk as f32);
-                    let dividend = powf(2.0, ((7 - factor)) as f32)* (baud as f32);
-                    div = div / dividend;
+                while factor < 4 {
+                    div = peripheral_clk as f32;
+                    let dividend = powf(2.0, (7 - factor) as f32) * (baud as f32);
+                    div /= dividend;
                     if div > 1.0 {
                         break;
                     }
-                }   
+                    factor += 1;
+                }
 
                 if div < 1.0 {
                     return Err(UartError::BaudRateConfiguration);
@@ -128,6 +304,31 @@ macro_rules! uart {
                 Ok(self)
             }
 
+            /// Lends the RX pin out as a GPIO input with pull-up, e.g. for `autobaud`
+            pub fn rx_as_input(&self) -> GpioInputPin {
+                Gpio0::input_pin(RX::PIN, Resistor::PullUp)
+            }
+
+            /// Switches the pin from `rx_as_input` back to the UART
+            pub fn restore_rx(&self, _rx: GpioInputPin) {
+                Gpio0::set_function(RX::PIN, RX::FUNCTION);
+            }
+
+            /// Detects the baud rate of a sync character (e.g. 0x55) on `rx` and programs it,
+            /// see `autobaud`, up to `autobaud::MAX_RATE`. Returns the rate, rounded to a standard
+            /// rate if within 5%.
+            pub fn autobaud<P: InputPin, C: Counter>(
+                &self,
+                rx: &P,
+                counter: &C,
+                peripheral_clk: u32,
+                timeout_us: u32,
+            ) -> Result<u32, AutobaudError> {
+                let baud = autobaud::snap_to_standard(autobaud::measure(rx, counter, timeout_us)?);
+                self.set_baud(peripheral_clk, baud).map_err(AutobaudError::Uart)?;
+                Ok(baud)
+            }
+
             pub fn enable_interrupts(&self, ints: &[Interrupts]) -> &Self {
                 let mut interrupt_final: u16 = 0;
                 
@@ -144,6 +345,64 @@ macro_rules! uart {
                 self
             }
 
+            /// Sets the RX timeout in character times, `Interrupts::RxTimeout` fires when the
+            /// line has been idle this long with data left in the RX FIFO. 0 disables it.
+            pub fn set_rx_timeout(&self, characters: u8) -> &Self {
+                unsafe {
+                    self.uart.ctrl.modify(|r, w| {
+                        w.bits(r.bits()).rx_to().bits(characters)
+                    })
+                }
+
+                self
+            }
+
+            /// Receives a message ended by line idle into `buf`. Blocks until data arrives,
+            /// then returns the message length once the line has been idle for the RX
+            /// timeout, or early when `buf` is full with the rest left in the FIFO.
+            pub fn read_until_idle(&self, buf: &mut [u8]) -> Result<usize, UartError> {
+                if self.uart.ctrl.read().rx_to().bits() == 0 {
+                    return Err(UartError::RxTimeoutDisabled);
+                }
+
+                let mut count = 0;
+                while count < buf.len() {
+                    if let Some(byte) = self.read_before_idle() {
+                        buf[count] = byte;
+                        count += 1;
+                    } else if self.take_idle() && count > 0 {
+                        // A flag left over from an earlier message doesn't end this one
+                        break;
+                    }
+                }
+
+                Ok(count)
+            }
+
+            /// Returns the next byte of a message ended by line idle. The RX timeout only
+            /// runs while the FIFO holds data, so the last byte is left in place until the
+            /// timeout has fired.
+            pub(crate) fn read_before_idle(&self) -> Option<u8> {
+                let timed_out = self.uart.int_fl.read().rx_timeout().bit();
+                let available = self.rx_fifo_cnt();
+
+                if available > 1 || (available == 1 && timed_out) {
+                    Some(self.read())
+                } else {
+                    None
+                }
+            }
+
+            /// Returns true, once, when the line has gone idle after the bytes taken with
+            /// `read_before_idle`
+            pub(crate) fn take_idle(&self) -> bool {
+                let idle = self.uart.int_fl.read().rx_timeout().bit() && self.rx_fifo_cnt() == 0;
+                if idle {
+                    self.clear_interrupt(&[Interrupts::RxTimeout]);
+                }
+                idle
+            }
+
             pub fn set_flow_control(&self, enable: bool, polarity: FlowControlPolarity) -> &Self {
                 unsafe {
                     self.uart.ctrl.modify(|r, w| {
@@ -169,8 +428,28 @@ macro_rules! uart {
                 self
             }
 
-            pub fn set_parity(&self, enable: bool, parity: Parity, level: ParityLevel) {
-                todo!("Configure parity")
+            /// Configures parity, `Parity::None` disables it like `enable` false does. `level`
+            /// selects whether the ones or the zeros of the character are counted.
+            pub fn set_parity(&self, enable: bool, parity: Parity, level: ParityLevel) -> &Self {
+                let enable = enable && !matches!(parity, Parity::None);
+
+                unsafe {
+                    self.uart.ctrl.modify(|r, w| {
+                        let w = w.bits(r.bits()).parity_en().bit(enable);
+                        let w = match parity {
+                            Parity::Odd => w.parity().odd(),
+                            Parity::Mark => w.parity().mark(),
+                            Parity::Space => w.parity().space(),
+                            Parity::Even | Parity::None => w.parity().even(),
+                        };
+                        match level {
+                            ParityLevel::Ones => w.parmd()._1(),
+                            ParityLevel::Zeros => w.parmd()._0(),
+                        }
+                    })
+                }
+
+                self
             }
 
             pub fn set_stop_bit(&self, stop: StopBits) -> &Self {
@@ -183,10 +462,11 @@ macro_rules! uart {
 
                 unsafe {
                     self.uart.ctrl.modify(|r, w| {
+                        // The 1.5 setting gives 1.5 stop bits for 5 bit characters, 2 otherwise
                         if stop_bool {
-                            w.bits(r.bits()).stopbits()._1()
-                        }else{
                             w.bits(r.bits()).stopbits()._1_5()
+                        }else{
+                            w.bits(r.bits()).stopbits()._1()
                         }
                     })
                 }
@@ -270,6 +550,10 @@ macro_rules! uart {
                 self.uart.status.read().tx_full().bit()
             }
 
+            pub fn get_interrupts(&self) -> u16 {
+                self.uart.int_fl.read().bits() as u16
+            }
+
             pub fn clear_interrupt(&self, ints: &[Interrupts]) {
                 let mut interrupt_final: u32 = 0;
                 
@@ -283,9 +567,511 @@ macro_rules! uart {
                     })
                 }
             }
+
+            /// Returns true once the TX FIFO and shift register have both drained
+            pub fn tx_idle(&self) -> bool {
+                let status = self.uart.status.read();
+                status.tx_empty().bit() && !status.tx_busy().bit()
+            }
+
+            /// Holds the TX line low (break condition) while `enable` is set
+            pub fn set_break(&self, enable: bool) {
+                unsafe {
+                    self.uart.ctrl.modify(|r, w| {
+                        w.bits(r.bits()).break_().bit(enable)
+                    })
+                }
+            }
+
+            /// Waits for pending data to go out, then transmits a break for `duration_us`
+            pub fn send_break<D: DelayUs<u32>>(&self, delay: &mut D, duration_us: u32) {
+                while !self.tx_idle() {}
+
+                self.set_break(true);
+                delay.delay_us(duration_us);
+                self.set_break(false);
+            }
+
+            /// Returns true if a break was received since the last call, clearing the flag
+            pub fn break_detected(&self) -> bool {
+                let detected = self.uart.int_fl.read().break_().bit();
+                if detected {
+                    self.clear_interrupt(&[Interrupts::Break]);
+                }
+                detected
+            }
+
+            /// Reads the next item from the RX stream, returns None if the FIFO is empty.
+            ///
+            /// A received break is stored by the hardware as a 0x00 character with a frame
+            /// error. While both the break and the frame error flag are pending the first
+            /// 0x00 read is reported as `RxEvent::Break`, and both flags are cleared. The
+            /// break is only reported once that character has been taken from the FIFO, so
+            /// it can't show up again as `Data(0)`.
+            ///
+            /// The flags don't say where in the FIFO the break is, so drain it faster than
+            /// a break follows a 0x00 data byte. Otherwise that byte is taken for the break.
+            pub fn read_event(&self) -> Option<RxEvent> {
+                if self.rx_fifo_cnt() == 0 {
+                    return None;
+                }
+
+                let flags = self.uart.int_fl.read();
+                let pending_break = flags.break_().bit() && flags.rx_frame_error().bit();
+
+                let byte = self.read();
+                if pending_break && byte == 0 {
+                    self.clear_interrupt(&[Interrupts::Break, Interrupts::RxFrameError]);
+                    return Some(RxEvent::Break);
+                }
+
+                Some(RxEvent::Data(byte))
+            }
+
+            /// Sends `selftest::PATTERN` at every combination of `bauds` and `formats` and
+            /// checks it comes back, TX has to be connected to RX. `report` is called with
+            /// the result of each run. Returns true if all runs passed.
+            ///
+            /// The UART configuration is restored afterwards, interrupts should be disabled
+            /// while the test runs.
+            pub fn self_test<F>(&self, peripheral_clk: u32, bauds: &[u32], formats: &[Format], mut report: F) -> bool
+            where
+                F: FnMut(&RunResult),
+            {
+                let ctrl = self.uart.ctrl.read().bits();
+                let baud0 = self.uart.baud0.read().bits();
+                let baud1 = self.uart.baud1.read().bits();
+                let mut passed = true;
+
+                for baud in bauds {
+                    for format in formats {
+                        let result = self.self_test_run(peripheral_clk, *baud, format);
+                        passed &= result.passed();
+                        report(&result);
+                    }
+                }
+
+                while !self.tx_idle() {}
+                unsafe {
+                    self.uart.baud0.write(|w| w.bits(baud0));
+                    self.uart.baud1.write(|w| w.bits(baud1));
+                    self.uart.ctrl.write(|w| w.bits(ctrl));
+                }
+                self.flush_rx_fifo();
+
+                passed
+            }
+
+            fn self_test_run<'a>(&self, peripheral_clk: u32, baud: u32, format: &'a Format) -> RunResult<'a> {
+                let mut result = RunResult::new(baud, format);
+
+                while !self.tx_idle() {}
+                if self.set_baud(peripheral_clk, baud).is_err() {
+                    result.configuration_error = true;
+                    return result;
+                }
+                self.set_char_size(format.char_size)
+                    .set_parity(true, format.parity, ParityLevel::Ones)
+                    .set_stop_bit(format.stop_bits)
+                    .enable();
+
+                self.flush_rx_fifo();
+                self.clear_interrupt(&[Interrupts::RxFrameError, Interrupts::RxParityError, Interrupts::RxOverrun]);
+
+                for (index, byte) in PATTERN.iter().enumerate() {
+                    $name::write(self, *byte);
+
+                    // The receiver samples the stop bit before the transmitter finishes it
+                    while !self.tx_idle() {}
+                    let received = if self.rx_fifo_cnt() > 0 { Some(self.read()) } else { None };
+
+                    let flags = self.uart.int_fl.read();
+                    result.frame_errors += flags.rx_frame_error().bit() as usize;
+                    result.parity_errors += flags.rx_parity_error().bit() as usize;
+                    result.overruns += flags.rx_overrun().bit() as usize;
+                    self.clear_interrupt(&[Interrupts::RxFrameError, Interrupts::RxParityError, Interrupts::RxOverrun]);
+
+                    result.record(index, *byte, received);
+                }
+
+                result
+            }
+
+            /// Prepares for sleep or deep sleep: waits for pending TX data, saves the UART
+            /// configuration, stops the UART clock and turns the RX pin into a GPIO that
+            /// wakes the device on the falling edge of a start bit.
+            ///
+            /// The character that wakes the device is lost, the host should retry or send
+            /// a wake-up character first. In sleep mode the GPIO0 interrupt has to be
+            /// enabled in the NVIC for the edge to end the WFI.
+            pub fn enable_wake_on_rx(&self) -> WakeState {
+                while !self.tx_idle() {}
+
+                let state = WakeState {
+                    rx: Gpio0::input_pin(RX::PIN, Resistor::PullUp),
+                    ctrl: self.uart.ctrl.read().bits(),
+                    baud0: self.uart.baud0.read().bits(),
+                    baud1: self.uart.baud1.read().bits(),
+                    thresh_ctrl: self.uart.thresh_ctrl.read().bits(),
+                    int_en: self.uart.int_en.read().bits(),
+                    dma: self.uart.dma.read().bits(),
+                };
+
+                state.rx.enable_edge_interrupt(InterruptEdgePolarity::Falling);
+                state.rx.enable_wakeup();
+
+                unsafe {
+                    (*max32660_pac::GCR::ptr()).perckcn0.modify(|r, w| {
+                        w.bits(r.bits()).$clock_disable().set_bit()
+                    });
+                }
+
+                state
+            }
+
+            /// Undoes `enable_wake_on_rx` after waking: restarts the UART clock, restores
+            /// the configuration and hands the RX pin back to the UART. The RX FIFO and
+            /// error flags are cleared of whatever the partial wake-up character left.
+            /// Returns true if RX activity woke the device.
+            pub fn restore_after_wake(&self, state: WakeState) -> bool {
+                let woke = state.rx.take_wakeup() | state.rx.is_interrupting();
+                state.rx.disable_wakeup();
+                state.rx.disable_interrupt();
+                state.rx.clear_interrupt();
+
+                unsafe {
+                    (*max32660_pac::GCR::ptr()).perckcn0.modify(|r, w| {
+                        w.bits(r.bits()).$clock_disable().clear_bit()
+                    });
+
+                    self.uart.baud0.write(|w| w.bits(state.baud0));
+                    self.uart.baud1.write(|w| w.bits(state.baud1));
+                    self.uart.thresh_ctrl.write(|w| w.bits(state.thresh_ctrl));
+                    self.uart.dma.write(|w| w.bits(state.dma));
+                    self.uart.ctrl.write(|w| w.bits(state.ctrl));
+                }
+
+                Gpio0::set_function(RX::PIN, RX::FUNCTION);
+
+                self.flush_rx_fifo();
+                unsafe {
+                    self.uart.int_fl.write(|w| w.bits(0xFFFF_FFFF));
+                    self.uart.int_en.write(|w| w.bits(state.int_en));
+                }
+
+                woke
+            }
+
+            /// Transmits `buffer` using a DMA channel, the UART is handed back by `Transfer::wait`
+            pub fn write_dma(self, mut channel: Channel, buffer: &'static [u8]) -> Transfer<Self, &'static [u8]> {
+                let request = $tx_request;
+                let fifo = Endpoint::fifo(&self.uart.fifo as *const _ as u32);
+                let src = Endpoint::memory(buffer.as_ptr() as u32);
+
+                unsafe {
+                    self.uart.dma.modify(|r, w| {
+                        w.bits(r.bits()).txdma_level().bits(TX_DMA_LEVEL).txdma_en().set_bit()
+                    });
+
+                    channel.start(request, src, fifo, buffer.len(), false);
+                }
+
+                Transfer::new(self, channel, buffer)
+            }
+
+            /// Fills `buffer` from the RX FIFO using a DMA channel
+            pub fn read_dma(self, mut channel: Channel, buffer: &'static mut [u8]) -> Transfer<Self, &'static mut [u8]> {
+                unsafe {
+                    self.start_rx_dma(&mut channel, buffer, false);
+                }
+
+                Transfer::new(self, channel, buffer)
+            }
+
+            /// Continuously receives into `buffer` as a ring, read out with `CircularTransfer::read`
+            pub fn read_dma_circular(self, mut channel: Channel, buffer: &'static mut [u8]) -> CircularTransfer<Self> {
+                unsafe {
+                    self.start_rx_dma(&mut channel, buffer, true);
+                }
+
+                CircularTransfer::new(self, channel, buffer)
+            }
+
+            unsafe fn start_rx_dma(&self, channel: &mut Channel, buffer: &mut [u8], circular: bool) {
+                let request = $rx_request;
+                let fifo = Endpoint::fifo(&self.uart.fifo as *const _ as u32);
+                let dst = Endpoint::memory(buffer.as_mut_ptr() as u32);
+
+                self.uart.dma.modify(|r, w| {
+                    w.bits(r.bits()).rxdma_level().bits(RX_DMA_LEVEL).rxdma_en().set_bit()
+                });
+
+                channel.start(request, fifo, dst, buffer.len(), circular);
+            }
+        }
+
+        impl<TX: TxPin<$UARTX>, RX: RxPin<$UARTX>, FC> DmaPeripheral for $name<TX, RX, FC> {
+            fn disable_dma(&self) {
+                unsafe {
+                    self.uart.dma.modify(|r, w| {
+                        w.bits(r.bits()).txdma_en().clear_bit().rxdma_en().clear_bit()
+                    });
+                }
+            }
+        }
+
+        impl<TX: TxPin<$UARTX>, RX: RxPin<$UARTX>, FC> $name<TX, RX, FC> {
+            /// Reads what the RX FIFO holds into `buf` without blocking, stopping at a break.
+            /// A break before the first byte is returned as an error.
+            fn read_available(&self, buf: &mut [u8]) -> Result<usize, UartError> {
+                let mut count = 0;
+
+                while count < buf.len() {
+                    // Leave a break for the next call once some data has been read
+                    if count > 0 && self.uart.int_fl.read().break_().bit() {
+                        break;
+                    }
+
+                    match self.read_event() {
+                        Some(RxEvent::Data(byte)) => {
+                            buf[count] = byte;
+                            count += 1;
+                        }
+                        Some(RxEvent::Break) => return Err(UartError::Break),
+                        None => break,
+                    }
+                }
+
+                Ok(count)
+            }
+
+            /// Fills the TX FIFO from `buf` without blocking, returns how many bytes fit
+            fn write_available(&self, buf: &[u8]) -> usize {
+                let mut count = 0;
+                while count < buf.len() && !self.tx_fifo_full() {
+                    $name::write(self, buf[count]);
+                    count += 1;
+                }
+                count
+            }
+
+            fn rx_ready(&self) -> bool {
+                self.rx_fifo_cnt() > 0 || self.uart.int_fl.read().break_().bit()
+            }
+        }
+
+        impl<TX: TxPin<$UARTX>, RX: RxPin<$UARTX>, FC> ErrorType for $name<TX, RX, FC> {
+            type Error = UartError;
+        }
+
+        impl<TX: TxPin<$UARTX>, RX: RxPin<$UARTX>, FC> embedded_io::Read for $name<TX, RX, FC> {
+            /// Blocks until at least one byte has been received
+            fn read(&mut self, buf: &mut [u8]) -> Result<usize, UartError> {
+                if buf.is_empty() {
+                    return Ok(0);
+                }
+
+                loop {
+                    let count = self.read_available(buf)?;
+                    if count > 0 {
+                        return Ok(count);
+                    }
+                }
+            }
+        }
+
+        impl<TX: TxPin<$UARTX>, RX: RxPin<$UARTX>, FC> embedded_io::Write for $name<TX, RX, FC> {
+            /// Blocks until at least one byte fits in the TX FIFO
+            fn write(&mut self, buf: &[u8]) -> Result<usize, UartError> {
+                if buf.is_empty() {
+                    return Ok(0);
+                }
+
+                loop {
+                    let count = self.write_available(buf);
+                    if count > 0 {
+                        return Ok(count);
+                    }
+                }
+            }
+
+            fn flush(&mut self) -> Result<(), UartError> {
+                while !self.tx_idle() {}
+                Ok(())
+            }
+        }
+
+        impl<TX: TxPin<$UARTX>, RX: RxPin<$UARTX>, FC> ReadReady for $name<TX, RX, FC> {
+            fn read_ready(&mut self) -> Result<bool, UartError> {
+                Ok(self.rx_ready())
+            }
+        }
+
+        impl<TX: TxPin<$UARTX>, RX: RxPin<$UARTX>, FC> WriteReady for $name<TX, RX, FC> {
+            fn write_ready(&mut self) -> Result<bool, UartError> {
+                Ok(!self.tx_fifo_full())
+            }
+        }
+
+        #[cfg(feature = "async")]
+        impl $name {
+            fn wakers() -> &'static Wakers {
+                static WAKERS: Wakers = Wakers::new();
+                &WAKERS
+            }
+
+            /// Wakes the tasks waiting in the async `Read`/`Write` impls, call from the UART
+            /// interrupt handler. The interrupts the async impls enabled are disabled again,
+            /// others are left alone.
+            pub fn on_interrupt() {
+                let uart = unsafe { &*<$UARTX>::ptr() };
+                let flags = uart.int_fl.read().bits();
+                let enabled = uart.int_en.read().bits();
+
+                let rx = (Interrupts::RxFifoThresh as u32 | Interrupts::RxTimeout as u32) & enabled;
+                let tx = Interrupts::TxFifoAlmostEmpty as u32 & enabled;
+
+                if flags & rx != 0 {
+                    unsafe { uart.int_en.modify(|r, w| w.bits(r.bits() & !rx)) };
+                    uart.int_fl.write(|w| unsafe { w.bits(rx) });
+                    <$name>::wakers().rx.wake();
+                }
+                if flags & tx != 0 {
+                    unsafe { uart.int_en.modify(|r, w| w.bits(r.bits() & !tx)) };
+                    uart.int_fl.write(|w| unsafe { w.bits(tx) });
+                    <$name>::wakers().tx.wake();
+                }
+            }
+        }
+
+        #[cfg(feature = "async")]
+        impl<TX: TxPin<$UARTX>, RX: RxPin<$UARTX>, FC> $name<TX, RX, FC> {
+            /// Clears stale `ints` flags and enables them, callers check their condition
+            /// again afterwards in case it came true in between
+            fn listen(&self, ints: u32) {
+                unsafe {
+                    self.uart.int_fl.write(|w| w.bits(ints));
+                    self.uart.int_en.modify(|r, w| w.bits(r.bits() | ints));
+                }
+            }
+        }
+
+        #[cfg(feature = "async")]
+        impl<TX: TxPin<$UARTX>, RX: RxPin<$UARTX>, FC> embedded_io_async::Read for $name<TX, RX, FC> {
+            /// Waits for at least one byte. Wakes on `RxFifoThresh` and `RxTimeout`, with an
+            /// RX FIFO threshold above 1 set an RX timeout so short messages are seen.
+            async fn read(&mut self, buf: &mut [u8]) -> Result<usize, UartError> {
+                if buf.is_empty() {
+                    return Ok(0);
+                }
+
+                core::future::poll_fn(|cx| {
+                    match self.read_available(buf) {
+                        Ok(0) => {}
+                        result => return Poll::Ready(result),
+                    }
+
+                    <$name>::wakers().rx.register(cx.waker());
+                    self.listen(Interrupts::RxFifoThresh as u32 | Interrupts::RxTimeout as u32);
+
+                    // Data that arrived before the interrupt was enabled doesn't raise it
+                    match self.read_available(buf) {
+                        Ok(0) => Poll::Pending,
+                        result => Poll::Ready(result),
+                    }
+                })
+                .await
+            }
+        }
+
+        #[cfg(feature = "async")]
+        impl<TX: TxPin<$UARTX>, RX: RxPin<$UARTX>, FC> embedded_io_async::Write for $name<TX, RX, FC> {
+            /// Waits until at least one byte fits in the TX FIFO
+            async fn write(&mut self, buf: &[u8]) -> Result<usize, UartError> {
+                if buf.is_empty() {
+                    return Ok(0);
+                }
+
+                core::future::poll_fn(|cx| {
+                    let count = self.write_available(buf);
+                    if count > 0 {
+                        return Poll::Ready(Ok(count));
+                    }
+
+                    <$name>::wakers().tx.register(cx.waker());
+                    self.listen(Interrupts::TxFifoAlmostEmpty as u32);
+
+                    match self.write_available(buf) {
+                        0 => Poll::Pending,
+                        count => Poll::Ready(Ok(count)),
+                    }
+                })
+                .await
+            }
+
+            /// Waits for the TX FIFO to drain to its last byte, then spins for the rest as
+            /// there is no transmit complete interrupt
+            async fn flush(&mut self) -> Result<(), UartError> {
+                core::future::poll_fn(|cx| {
+                    if self.uart.status.read().tx_fifo_cnt().bits() <= 1 {
+                        return Poll::Ready(());
+                    }
+
+                    <$name>::wakers().tx.register(cx.waker());
+                    self.listen(Interrupts::TxFifoAlmostEmpty as u32);
+
+                    if self.uart.status.read().tx_fifo_cnt().bits() <= 1 {
+                        Poll::Ready(())
+                    } else {
+                        Poll::Pending
+                    }
+                })
+                .await;
+
+                while !self.tx_idle() {}
+                Ok(())
+            }
+        }
+
+        impl<TX: TxPin<$UARTX>, RX: RxPin<$UARTX>, FC> BreakControl for $name<TX, RX, FC> {
+            fn set_break(&mut self, enable: bool) {
+                $name::set_break(self, enable)
+            }
+        }
+
+        impl<TX: TxPin<$UARTX>, RX: RxPin<$UARTX>, FC> serial::Read<u8> for $name<TX, RX, FC> {
+            type Error = UartError;
+
+            fn read(&mut self) -> nb::Result<u8, UartError> {
+                match self.read_event() {
+                    Some(RxEvent::Data(byte)) => Ok(byte),
+                    Some(RxEvent::Break) => Err(nb::Error::Other(UartError::Break)),
+                    None => Err(nb::Error::WouldBlock),
+                }
+            }
+        }
+
+        impl<TX: TxPin<$UARTX>, RX: RxPin<$UARTX>, FC> serial::Write<u8> for $name<TX, RX, FC> {
+            type Error = UartError;
+
+            fn write(&mut self, word: u8) -> nb::Result<(), UartError> {
+                if self.tx_fifo_full() {
+                    return Err(nb::Error::WouldBlock);
+                }
+                $name::write(self, word);
+                Ok(())
+            }
+
+            fn flush(&mut self) -> nb::Result<(), UartError> {
+                if self.tx_idle() {
+                    Ok(())
+                } else {
+                    Err(nb::Error::WouldBlock)
+                }
+            }
         }
     }
 }
 
-uart!(max32660_pac::UART0, Uart0);
-uart!(max32660_pac::UART1, Uart1);
\ No newline at end of file
+uart!(max32660_pac::UART0, Uart0, P0_4, P0_5, Request::Uart0Tx, Request::Uart0Rx, uart0d);
+uart!(max32660_pac::UART1, Uart1, P0_10, P0_11, Request::Uart1Tx, Request::Uart1Rx, uart1d);
\ No newline at end of file
@@ -0,0 +1,212 @@
+//! Baud rate detection from the edges of a sync character on the RX pin.
+//!
+//! The RX line is sampled through an `InputPin` and every edge is timestamped with a
+//! free-running `Counter`. The shortest interval between edges is one bit time; dividing
+//! the whole character span by the number of bits it contains averages out the polling
+//! jitter. 0x55 gives an edge on every bit and is the recommended sync character.
+//!
+//! Edges are only seen when the loop samples the pin, so every edge is late by up to one
+//! pass of the loop, about a microsecond with the core at 96 MHz. Above `MAX_RATE` that
+//! jitter approaches a whole bit time, so faster characters are refused with `TooFast`
+//! instead of being reported at a wrong rate. Run the counter undivided for the best
+//! resolution, a prescaler is taken into account by `Counter::frequency`.
+//!
+//! The UART owns its RX pin, borrow it as a GPIO input with `rx_as_input` and hand it back
+//! with `restore_rx` once the rate has been detected.
+
+use embedded_hal::digital::v2::InputPin;
+
+use super::UartError;
+use crate::timer::Counter;
+
+/// A character with start and stop bit has at most 10 edges
+const MAX_EDGES: usize = 10;
+
+/// The character is over once the line has been high this many shortest intervals
+const END_OF_CHARACTER_BITS: u32 = 12;
+
+/// Fastest rate `measure` can time reliably by polling the pin
+pub const MAX_RATE: u32 = 115_200;
+
+/// Rates `snap_to_standard` rounds to
+pub const STANDARD_RATES: [u32; 9] = [1200, 2400, 4800, 9600, 14400, 19200, 38400, 57600, 115200];
+
+#[derive(Debug)]
+pub enum AutobaudError {
+    /// No complete character was seen before the timeout
+    Timeout,
+    /// Fewer than two edges were seen, so no bit time could be measured
+    NoEdges,
+    /// The character was faster than `MAX_RATE`, too fast to be measured by polling
+    TooFast,
+    Uart(UartError),
+}
+
+fn elapsed(start: u32, now: u32) -> u32 {
+    now.wrapping_sub(start)
+}
+
+/// Waits for a character on `rx` and returns its baud rate.
+///
+/// Fails with `Timeout` if no start bit arrives within `timeout_us` and with `TooFast` if
+/// the character was sent above `MAX_RATE`.
+pub fn measure<P, C>(rx: &P, counter: &C, timeout_us: u32) -> Result<u32, AutobaudError>
+where
+    P: InputPin,
+    C: Counter,
+{
+    let frequency = counter.frequency();
+    let timeout = (timeout_us as u64 * frequency as u64 / 1_000_000) as u32;
+    let high = || rx.is_high().unwrap_or(true);
+
+    // Wait for the falling edge of the start bit, ignoring a line that starts low
+    let start = counter.count();
+    let mut level = high();
+    loop {
+        let now_high = high();
+        if level && !now_high {
+            break;
+        }
+        level = now_high;
+
+        if elapsed(start, counter.count()) > timeout {
+            return Err(AutobaudError::Timeout);
+        }
+    }
+
+    let mut edges = [0u32; MAX_EDGES];
+    edges[0] = counter.count();
+    let mut edge_count = 1;
+    let mut level = false;
+    let mut shortest = u32::MAX;
+
+    while edge_count < MAX_EDGES {
+        let now = counter.count();
+        let now_high = high();
+
+        if now_high != level {
+            level = now_high;
+            shortest = shortest.min(elapsed(edges[edge_count - 1], now));
+            edges[edge_count] = now;
+            edge_count += 1;
+        } else if level && shortest != u32::MAX {
+            // The stop bit is high, a long high period ends the character
+            let idle = elapsed(edges[edge_count - 1], now);
+            if idle / END_OF_CHARACTER_BITS > shortest {
+                break;
+            }
+        }
+
+        if elapsed(edges[0], now) > timeout {
+            return Err(AutobaudError::Timeout);
+        }
+    }
+
+    if edge_count < 2 || shortest == 0 {
+        return Err(AutobaudError::NoEdges);
+    }
+
+    let span = elapsed(edges[0], edges[edge_count - 1]);
+    let bits = (span + shortest / 2) / shortest;
+    let bit_ticks = span / bits.max(1);
+    if bit_ticks == 0 {
+        return Err(AutobaudError::NoEdges);
+    }
+
+    let baud = (frequency + bit_ticks / 2) / bit_ticks;
+    // Allow the same 5% `snap_to_standard` does before refusing the rate
+    if baud > MAX_RATE + MAX_RATE / 20 {
+        return Err(AutobaudError::TooFast);
+    }
+
+    Ok(baud)
+}
+
+/// Returns the standard rate within 5% of `baud`, or `baud` itself if there is none
+pub fn snap_to_standard(baud: u32) -> u32 {
+    STANDARD_RATES
+        .iter()
+        .copied()
+        .find(|rate| baud.abs_diff(*rate) <= rate / 20)
+        .unwrap_or(baud)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+    use core::convert::Infallible;
+
+    const FREQUENCY: u32 = 96_000_000;
+
+    /// Plays 0x55 at `baud` on a line that every `count` moves forward by a few ticks
+    struct Line {
+        now: Cell<u32>,
+        bit_ticks: u32,
+    }
+
+    impl Line {
+        const START: u32 = 1_000;
+        const STEP: u32 = 10;
+
+        fn new(baud: u32) -> Self {
+            Line { now: Cell::new(0), bit_ticks: FREQUENCY / baud }
+        }
+    }
+
+    impl Counter for Line {
+        fn count(&self) -> u32 {
+            self.now.set(self.now.get() + Self::STEP);
+            self.now.get()
+        }
+
+        fn frequency(&self) -> u32 {
+            FREQUENCY
+        }
+    }
+
+    impl InputPin for Line {
+        type Error = Infallible;
+
+        fn is_high(&self) -> Result<bool, Infallible> {
+            let now = self.now.get();
+            if now < Self::START {
+                return Ok(true);
+            }
+            // Start bit, 0x55 LSB first, then the stop bit and idle
+            let bit = (now - Self::START) / self.bit_ticks;
+            Ok(match bit {
+                0 => false,
+                1..=8 => 0x55 >> (bit - 1) & 1 == 1,
+                _ => true,
+            })
+        }
+
+        fn is_low(&self) -> Result<bool, Infallible> {
+            self.is_high().map(|high| !high)
+        }
+    }
+
+    #[test]
+    fn measures_standard_rates() {
+        for baud in [9600, 57600, 115200] {
+            let line = Line::new(baud);
+            let measured = measure(&line, &line, 100_000).unwrap();
+            assert_eq!(snap_to_standard(measured), baud);
+        }
+    }
+
+    #[test]
+    fn refuses_rates_above_the_maximum() {
+        let line = Line::new(460800);
+        assert!(matches!(measure(&line, &line, 100_000), Err(AutobaudError::TooFast)));
+    }
+
+    #[test]
+    fn times_out_on_an_idle_line() {
+        // Time only moves on `counter`, so `idle` never reaches its start bit
+        let counter = Line::new(9600);
+        let idle = Line::new(9600);
+        assert!(matches!(measure(&idle, &counter, 1_000), Err(AutobaudError::Timeout)));
+    }
+}
@@ -0,0 +1,122 @@
+//! Loopback self-test for production test, run with `Uart0::self_test`.
+//!
+//! The MAX32660 UARTs have no internal loopback, so TX has to be connected to RX on the
+//! board, through a jumper or the test fixture. A test pattern is sent one character at a
+//! time at every combination of the given baud rates and formats, and each character
+//! read back is compared with what was sent.
+
+use super::{CharSize, Parity, StopBits};
+
+/// Characters sent in each run, covering all-zeros, all-ones and alternating bits
+pub const PATTERN: [u8; 8] = [0x00, 0xFF, 0x55, 0xAA, 0x0F, 0xF0, 0x81, 0x7E];
+
+/// Rates swept by `DEFAULT_BAUDS`
+pub const DEFAULT_BAUDS: [u32; 8] = [9600, 19200, 38400, 57600, 115200, 230400, 460800, 921600];
+
+/// 8N1, 8E1, 8O1, 7E1 and 8N2
+pub const DEFAULT_FORMATS: [Format; 5] = [
+    Format::new(CharSize::_8, Parity::None, StopBits::_1),
+    Format::new(CharSize::_8, Parity::Even, StopBits::_1),
+    Format::new(CharSize::_8, Parity::Odd, StopBits::_1),
+    Format::new(CharSize::_7, Parity::Even, StopBits::_1),
+    Format::new(CharSize::_8, Parity::None, StopBits::_2),
+];
+
+/// A character format to test
+#[derive(Copy, Clone)]
+pub struct Format {
+    pub char_size: CharSize,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+impl Format {
+    pub const fn new(char_size: CharSize, parity: Parity, stop_bits: StopBits) -> Self {
+        Format {
+            char_size,
+            parity,
+            stop_bits,
+        }
+    }
+
+    /// Bits of a byte that make it through a character of this size
+    pub(crate) fn data_mask(&self) -> u8 {
+        match self.char_size {
+            CharSize::_5 => 0x1F,
+            CharSize::_6 => 0x3F,
+            CharSize::_7 => 0x7F,
+            CharSize::_8 => 0xFF,
+        }
+    }
+}
+
+/// The first character that didn't come back as sent
+#[derive(Debug, Copy, Clone)]
+pub struct Mismatch {
+    /// Position in `PATTERN`
+    pub index: usize,
+    pub sent: u8,
+    /// None if nothing was received
+    pub received: Option<u8>,
+}
+
+/// Outcome of one baud rate and format combination
+#[derive(Copy, Clone)]
+pub struct RunResult<'a> {
+    pub baud: u32,
+    pub format: &'a Format,
+    /// The baud rate can't be generated from the peripheral clock, nothing was sent
+    pub configuration_error: bool,
+    pub sent: usize,
+    pub received: usize,
+    pub mismatches: usize,
+    pub first_mismatch: Option<Mismatch>,
+    pub frame_errors: usize,
+    pub parity_errors: usize,
+    pub overruns: usize,
+}
+
+impl<'a> RunResult<'a> {
+    pub(crate) fn new(baud: u32, format: &'a Format) -> Self {
+        RunResult {
+            baud,
+            format,
+            configuration_error: false,
+            sent: 0,
+            received: 0,
+            mismatches: 0,
+            first_mismatch: None,
+            frame_errors: 0,
+            parity_errors: 0,
+            overruns: 0,
+        }
+    }
+
+    /// Records one character sent and what came back
+    pub(crate) fn record(&mut self, index: usize, sent: u8, received: Option<u8>) {
+        self.sent += 1;
+        if received.is_some() {
+            self.received += 1;
+        }
+
+        let expected = sent & self.format.data_mask();
+        if received.map(|byte| byte & self.format.data_mask()) != Some(expected) {
+            self.mismatches += 1;
+            if self.first_mismatch.is_none() {
+                self.first_mismatch = Some(Mismatch {
+                    index,
+                    sent: expected,
+                    received,
+                });
+            }
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        !self.configuration_error
+            && self.mismatches == 0
+            && self.frame_errors == 0
+            && self.parity_errors == 0
+            && self.overruns == 0
+    }
+}
@@ -0,0 +1,653 @@
+//! XMODEM-CRC and YMODEM receiver for firmware updates over `Uart0`/`Uart1`.
+//!
+//! Received blocks are streamed to a `Sink`, e.g. a flash writer, as soon as their CRC
+//! checks out, so no image sized buffer is needed. Timeouts are measured with a free-running
+//! `timer::Counter`, which together with the `serial` traits lets the protocol run on the
+//! host against a simulated sender.
+//!
+//! ```ignore
+//! timer.start_free_running();
+//! let mut receiver = Receiver::new(uart, timer);
+//! let bytes = receiver.receive_ymodem(&mut flash_writer)?;
+//! ```
+
+use embedded_hal::serial::{Read, Write};
+
+use crate::crc;
+use crate::timer::Counter;
+use crate::uart::UartError;
+
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+/// Sent instead of NAK to ask for CRC-16 instead of checksum blocks
+const CRC_REQUEST: u8 = b'C';
+
+const SHORT_BLOCK: usize = 128;
+const LONG_BLOCK: usize = 1024;
+
+/// Time allowed between the characters of a block
+const CHAR_TIMEOUT_US: u32 = 1_000_000;
+
+/// Default time to wait for the next block before asking again
+pub const DEFAULT_TIMEOUT_US: u32 = 3_000_000;
+
+/// Default number of consecutive timeouts or bad blocks before giving up
+pub const DEFAULT_RETRIES: u8 = 10;
+
+/// CANs sent to abort a transfer, senders need at least two in a row
+const CANCEL_COUNT: usize = 3;
+
+#[derive(Debug)]
+pub enum XmodemError<E> {
+    /// No valid block arrived within the allowed retries
+    Timeout,
+    /// The sender aborted the transfer
+    Cancelled,
+    /// A block arrived out of order, the transfer was cancelled
+    Sequence,
+    /// The YMODEM header block couldn't be parsed, the transfer was cancelled
+    Header,
+    /// The sink failed, the transfer was cancelled
+    Sink(E),
+    Uart(UartError),
+}
+
+/// Destination of the received data
+pub trait Sink {
+    type Error;
+
+    /// Called with the name and size from a YMODEM header before each file's data
+    fn start(&mut self, _name: &str, _size: Option<u32>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Writes `data` at `offset` from the start of the file. XMODEM pads the last block
+    /// with 0x1A, YMODEM data is cut to the size from the header if there was one.
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Called once a file has been completely received
+    fn finish(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// CRC-16/XMODEM (polynomial 0x1021, initial value 0)
+pub fn crc16(data: &[u8]) -> u16 {
+    crc::XMODEM.checksum(data)
+}
+
+enum Packet {
+    /// A block with a good CRC, its data is in `Receiver::block`
+    Block { number: u8, len: usize },
+    EndOfFile,
+    Cancel,
+    /// Nothing arrived in time
+    Timeout,
+    /// A corrupted or incomplete block
+    Bad,
+}
+
+pub struct Receiver<S, C> {
+    uart: S,
+    clock: C,
+    timeout_us: u32,
+    retries: u8,
+    block: [u8; LONG_BLOCK],
+}
+
+impl<S, C> Receiver<S, C>
+where
+    S: Read<u8, Error = UartError> + Write<u8, Error = UartError>,
+    C: Counter,
+{
+    /// Creates the receiver, `clock` has to be running
+    pub fn new(uart: S, clock: C) -> Self {
+        Receiver {
+            uart,
+            clock,
+            timeout_us: DEFAULT_TIMEOUT_US,
+            retries: DEFAULT_RETRIES,
+            block: [0; LONG_BLOCK],
+        }
+    }
+
+    /// Releases the UART and clock
+    pub fn free(self) -> (S, C) {
+        (self.uart, self.clock)
+    }
+
+    /// Sets how long to wait for each block and how often to ask again. The retries
+    /// also give the user time to start the sender.
+    pub fn set_timeout(&mut self, timeout_us: u32, retries: u8) -> &mut Self {
+        self.timeout_us = timeout_us;
+        self.retries = retries;
+        self
+    }
+
+    /// Receives a single file with XMODEM-CRC, 128 or 1024 byte blocks. Returns the
+    /// number of bytes written to the sink.
+    pub fn receive_xmodem<K: Sink>(&mut self, sink: &mut K) -> Result<u32, XmodemError<K::Error>> {
+        let received = self.receive_file(sink, &[CRC_REQUEST], None)?;
+        sink.finish().map_err(|e| self.abort(XmodemError::Sink(e)))?;
+        Ok(received)
+    }
+
+    /// Receives a YMODEM batch, the sink's `start` is called for each file. Returns the
+    /// number of bytes written to the sink.
+    pub fn receive_ymodem<K: Sink>(&mut self, sink: &mut K) -> Result<u32, XmodemError<K::Error>> {
+        let mut total = 0;
+
+        loop {
+            let len = self.receive_header()?;
+            let (name, size) = match parse_header(&self.block[..len]) {
+                Some(header) => header,
+                None => return Err(self.abort(XmodemError::Header)),
+            };
+
+            // An empty name ends the batch
+            if name.is_empty() {
+                self.send(&[ACK])?;
+                return Ok(total);
+            }
+
+            sink.start(name, size).map_err(|e| self.abort(XmodemError::Sink(e)))?;
+            total += self.receive_file(sink, &[ACK, CRC_REQUEST], size)?;
+            sink.finish().map_err(|e| self.abort(XmodemError::Sink(e)))?;
+        }
+    }
+
+    /// Tells the sender to abort the transfer
+    pub fn cancel(&mut self) -> Result<(), UartError> {
+        for _ in 0..CANCEL_COUNT {
+            nb::block!(self.uart.write(CAN))?;
+        }
+        nb::block!(self.uart.flush())
+    }
+
+    fn abort<E>(&mut self, error: XmodemError<E>) -> XmodemError<E> {
+        match self.cancel() {
+            Ok(()) => error,
+            Err(e) => XmodemError::Uart(e),
+        }
+    }
+
+    fn send<E>(&mut self, bytes: &[u8]) -> Result<(), XmodemError<E>> {
+        for byte in bytes {
+            nb::block!(self.uart.write(*byte)).map_err(XmodemError::Uart)?;
+        }
+        Ok(())
+    }
+
+    /// Waits for YMODEM block 0, returns its length
+    fn receive_header<E>(&mut self) -> Result<usize, XmodemError<E>> {
+        let mut failures = 0;
+
+        loop {
+            self.send(&[CRC_REQUEST])?;
+
+            match self.read_packet() {
+                Packet::Block { number: 0, len } => return Ok(len),
+                Packet::Cancel => return Err(XmodemError::Cancelled),
+                // A repeated EOT or last block whose ACK got lost
+                Packet::EndOfFile | Packet::Block { .. } => self.send(&[ACK])?,
+                Packet::Timeout | Packet::Bad => {
+                    failures += 1;
+                    if failures > self.retries {
+                        return Err(self.abort(XmodemError::Timeout));
+                    }
+                    self.purge();
+                }
+            }
+        }
+    }
+
+    /// Receives blocks 1.. until EOT, answering the first block request with `request`.
+    /// Data beyond `size` is dropped.
+    fn receive_file<K: Sink>(
+        &mut self,
+        sink: &mut K,
+        request: &'static [u8],
+        size: Option<u32>,
+    ) -> Result<u32, XmodemError<K::Error>> {
+        let mut expected: u8 = 1;
+        let mut offset: u32 = 0;
+        let mut reply = request;
+        let mut failures = 0;
+
+        loop {
+            self.send(reply)?;
+
+            match self.read_packet() {
+                Packet::Block { number, len } if number == expected => {
+                    let len = match size {
+                        Some(size) => (size.saturating_sub(offset) as usize).min(len),
+                        None => len,
+                    };
+                    if len > 0 {
+                        sink.write(offset, &self.block[..len])
+                            .map_err(|e| self.abort(XmodemError::Sink(e)))?;
+                    }
+
+                    offset += len as u32;
+                    expected = expected.wrapping_add(1);
+                    reply = &[ACK];
+                    failures = 0;
+                }
+                // Our ACK got lost and the sender repeated the block. In YMODEM this can
+                // also be the header, the sender then waits for another request.
+                Packet::Block { number, .. } if number == expected.wrapping_sub(1) => {
+                    reply = if expected == 1 { request } else { &[ACK] };
+                }
+                Packet::Block { .. } => return Err(self.abort(XmodemError::Sequence)),
+                Packet::EndOfFile => {
+                    self.send(&[ACK])?;
+                    return Ok(offset);
+                }
+                Packet::Cancel => return Err(XmodemError::Cancelled),
+                packet @ (Packet::Timeout | Packet::Bad) => {
+                    failures += 1;
+                    if failures > self.retries {
+                        return Err(self.abort(XmodemError::Timeout));
+                    }
+                    self.purge();
+                    // Until the first block arrives the sender may still be waiting to start.
+                    // A damaged block shows it has started, and in YMODEM the request would
+                    // ACK the block.
+                    let waiting = matches!(packet, Packet::Timeout) && expected == 1 && offset == 0;
+                    reply = if waiting { request } else { &[NAK] };
+                }
+            }
+        }
+    }
+
+    /// Reads one byte, waiting at most `timeout_us`. Receive errors are ignored, the
+    /// block's CRC catches the damage.
+    fn read_byte(&mut self, timeout_us: u32) -> Option<u8> {
+        let ticks = (timeout_us as u64 * self.clock.frequency() as u64 / 1_000_000) as u32;
+        let start = self.clock.count();
+
+        loop {
+            if let Ok(byte) = self.uart.read() {
+                return Some(byte);
+            }
+            if self.clock.count().wrapping_sub(start) > ticks {
+                return None;
+            }
+        }
+    }
+
+    /// Drops everything until the line has been quiet for a character timeout
+    fn purge(&mut self) {
+        while self.read_byte(CHAR_TIMEOUT_US).is_some() {}
+    }
+
+    fn read_packet(&mut self) -> Packet {
+        let len = match self.read_byte(self.timeout_us) {
+            Some(SOH) => SHORT_BLOCK,
+            Some(STX) => LONG_BLOCK,
+            Some(EOT) => return Packet::EndOfFile,
+            Some(CAN) => {
+                // A single CAN may be line noise
+                return match self.read_byte(CHAR_TIMEOUT_US) {
+                    Some(CAN) => Packet::Cancel,
+                    _ => Packet::Bad,
+                };
+            }
+            Some(_) => return Packet::Bad,
+            None => return Packet::Timeout,
+        };
+
+        let mut header = [0; 2];
+        for byte in header.iter_mut() {
+            match self.read_byte(CHAR_TIMEOUT_US) {
+                Some(b) => *byte = b,
+                None => return Packet::Bad,
+            }
+        }
+
+        for index in 0..len {
+            match self.read_byte(CHAR_TIMEOUT_US) {
+                Some(b) => self.block[index] = b,
+                None => return Packet::Bad,
+            }
+        }
+
+        let mut crc = [0; 2];
+        for byte in crc.iter_mut() {
+            match self.read_byte(CHAR_TIMEOUT_US) {
+                Some(b) => *byte = b,
+                None => return Packet::Bad,
+            }
+        }
+
+        if header[0] != !header[1] || crc16(&self.block[..len]).to_be_bytes() != crc {
+            return Packet::Bad;
+        }
+
+        Packet::Block {
+            number: header[0],
+            len,
+        }
+    }
+}
+
+/// Splits a YMODEM header into file name and size. The name is NUL terminated, followed
+/// by the size in decimal and optional fields separated by spaces.
+fn parse_header(block: &[u8]) -> Option<(&str, Option<u32>)> {
+    let name_end = block.iter().position(|b| *b == 0)?;
+    let name = core::str::from_utf8(&block[..name_end]).ok()?;
+
+    let fields = &block[name_end + 1..];
+    let digits = fields.iter().take_while(|b| b.is_ascii_digit()).count();
+    let size = if digits == 0 {
+        None
+    } else {
+        core::str::from_utf8(&fields[..digits]).ok()?.parse().ok()
+    };
+
+    Some((name, size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+    use std::collections::VecDeque;
+    use std::string::{String, ToString};
+    use std::vec::Vec;
+
+    /// Plays back one scripted transmission each time the receiver has answered
+    struct SimulatedSender {
+        script: VecDeque<Vec<u8>>,
+        line: VecDeque<u8>,
+        /// Everything the receiver sent
+        replies: Vec<u8>,
+        answered: bool,
+    }
+
+    impl SimulatedSender {
+        fn new(script: Vec<Vec<u8>>) -> Self {
+            SimulatedSender {
+                script: script.into(),
+                line: VecDeque::new(),
+                replies: Vec::new(),
+                answered: false,
+            }
+        }
+    }
+
+    impl Read<u8> for SimulatedSender {
+        type Error = UartError;
+
+        fn read(&mut self) -> nb::Result<u8, UartError> {
+            if self.line.is_empty() && self.answered {
+                self.answered = false;
+                if let Some(bytes) = self.script.pop_front() {
+                    self.line.extend(bytes);
+                }
+            }
+            self.line.pop_front().ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    impl Write<u8> for SimulatedSender {
+        type Error = UartError;
+
+        fn write(&mut self, byte: u8) -> nb::Result<(), UartError> {
+            self.replies.push(byte);
+            self.answered = true;
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), UartError> {
+            Ok(())
+        }
+    }
+
+    /// Advances 1 ms each time it is read, so timeouts pass quickly
+    #[derive(Default)]
+    struct FakeClock {
+        now: Cell<u32>,
+    }
+
+    impl Counter for FakeClock {
+        fn count(&self) -> u32 {
+            self.now.set(self.now.get().wrapping_add(1_000));
+            self.now.get()
+        }
+
+        fn frequency(&self) -> u32 {
+            1_000_000
+        }
+    }
+
+    #[derive(Default)]
+    struct MemorySink {
+        data: Vec<u8>,
+        files: Vec<(String, Option<u32>)>,
+        finished: usize,
+    }
+
+    impl Sink for MemorySink {
+        type Error = ();
+
+        fn start(&mut self, name: &str, size: Option<u32>) -> Result<(), ()> {
+            self.files.push((name.to_string(), size));
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), ()> {
+            assert_eq!(offset as usize, self.data.len());
+            self.data.extend_from_slice(data);
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<(), ()> {
+            self.finished += 1;
+            Ok(())
+        }
+    }
+
+    /// A block as the sender puts it on the line, `data` padded with 0x1A
+    fn block(number: u8, size: usize, data: &[u8]) -> Vec<u8> {
+        let mut payload = vec![0x1A; size];
+        payload[..data.len()].copy_from_slice(data);
+
+        let mut bytes = vec![if size == SHORT_BLOCK { SOH } else { STX }, number, !number];
+        bytes.extend(&payload);
+        bytes.extend(crc16(&payload).to_be_bytes());
+        bytes
+    }
+
+    fn pattern(len: usize, seed: u8) -> Vec<u8> {
+        (0..len).map(|i| (i as u8).wrapping_mul(7).wrapping_add(seed)).collect()
+    }
+
+    fn receiver(script: Vec<Vec<u8>>) -> Receiver<SimulatedSender, FakeClock> {
+        let mut receiver = Receiver::new(SimulatedSender::new(script), FakeClock::default());
+        receiver.set_timeout(DEFAULT_TIMEOUT_US, 2);
+        receiver
+    }
+
+    fn replies(receiver: Receiver<SimulatedSender, FakeClock>) -> Vec<u8> {
+        receiver.free().0.replies
+    }
+
+    #[test]
+    fn receives_short_and_long_blocks() {
+        let first = pattern(SHORT_BLOCK, 1);
+        let second = pattern(LONG_BLOCK, 2);
+        let mut receiver = receiver(vec![
+            block(1, SHORT_BLOCK, &first),
+            block(2, LONG_BLOCK, &second),
+            vec![EOT],
+        ]);
+        let mut sink = MemorySink::default();
+
+        assert_eq!(receiver.receive_xmodem(&mut sink).unwrap(), (SHORT_BLOCK + LONG_BLOCK) as u32);
+        assert_eq!(sink.data, [first, second].concat());
+        assert_eq!(sink.finished, 1);
+        assert_eq!(replies(receiver), [CRC_REQUEST, ACK, ACK, ACK]);
+    }
+
+    #[test]
+    fn bad_crc_is_nakked_and_resent() {
+        let second = pattern(SHORT_BLOCK, 2);
+        let mut corrupted = block(2, SHORT_BLOCK, &second);
+        corrupted[10] ^= 0x01;
+        let mut receiver = receiver(vec![
+            block(1, SHORT_BLOCK, &[0x55]),
+            corrupted,
+            block(2, SHORT_BLOCK, &second),
+            vec![EOT],
+        ]);
+        let mut sink = MemorySink::default();
+
+        receiver.receive_xmodem(&mut sink).unwrap();
+        assert_eq!(&sink.data[SHORT_BLOCK..], &second[..]);
+        assert_eq!(replies(receiver), [CRC_REQUEST, ACK, NAK, ACK, ACK]);
+    }
+
+    #[test]
+    fn bad_first_block_is_nakked() {
+        let first = pattern(SHORT_BLOCK, 1);
+        let mut corrupted = block(1, SHORT_BLOCK, &first);
+        corrupted[10] ^= 0x01;
+        let mut receiver = receiver(vec![corrupted, block(1, SHORT_BLOCK, &first), vec![EOT]]);
+        let mut sink = MemorySink::default();
+
+        assert_eq!(receiver.receive_xmodem(&mut sink).unwrap(), SHORT_BLOCK as u32);
+        assert_eq!(sink.data, first);
+        assert_eq!(replies(receiver), [CRC_REQUEST, NAK, ACK, ACK]);
+    }
+
+    #[test]
+    fn ymodem_bad_first_block_is_nakked() {
+        let data = pattern(SHORT_BLOCK, 4);
+        let mut corrupted = block(1, SHORT_BLOCK, &data);
+        corrupted[10] ^= 0x01;
+        let mut receiver = receiver(vec![
+            block(0, SHORT_BLOCK, b"a.bin\x00128"),
+            corrupted,
+            block(1, SHORT_BLOCK, &data),
+            vec![EOT],
+            block(0, SHORT_BLOCK, &[0; SHORT_BLOCK]),
+        ]);
+        let mut sink = MemorySink::default();
+
+        assert_eq!(receiver.receive_ymodem(&mut sink).unwrap(), SHORT_BLOCK as u32);
+        assert_eq!(sink.data, data);
+        assert_eq!(
+            replies(receiver),
+            [CRC_REQUEST, ACK, CRC_REQUEST, NAK, ACK, ACK, CRC_REQUEST, ACK]
+        );
+    }
+
+    #[test]
+    fn first_block_timeout_repeats_the_request() {
+        let mut receiver = receiver(vec![vec![], block(1, SHORT_BLOCK, &[1]), vec![EOT]]);
+        let mut sink = MemorySink::default();
+
+        assert_eq!(receiver.receive_xmodem(&mut sink).unwrap(), SHORT_BLOCK as u32);
+        assert_eq!(replies(receiver), [CRC_REQUEST, CRC_REQUEST, ACK, ACK]);
+    }
+
+    #[test]
+    fn bad_block_number_complement_is_rejected() {
+        let mut damaged = block(1, SHORT_BLOCK, &[1]);
+        damaged[2] = 0;
+        let mut receiver = receiver(vec![damaged, block(1, SHORT_BLOCK, &[1]), vec![EOT]]);
+        let mut sink = MemorySink::default();
+
+        assert_eq!(receiver.receive_xmodem(&mut sink).unwrap(), SHORT_BLOCK as u32);
+        assert_eq!(replies(receiver), [CRC_REQUEST, NAK, ACK, ACK]);
+    }
+
+    #[test]
+    fn duplicate_block_is_acked_and_dropped() {
+        let first = pattern(SHORT_BLOCK, 1);
+        let second = pattern(SHORT_BLOCK, 2);
+        let mut receiver = receiver(vec![
+            block(1, SHORT_BLOCK, &first),
+            block(1, SHORT_BLOCK, &first),
+            block(2, SHORT_BLOCK, &second),
+            vec![EOT],
+        ]);
+        let mut sink = MemorySink::default();
+
+        assert_eq!(receiver.receive_xmodem(&mut sink).unwrap(), 2 * SHORT_BLOCK as u32);
+        assert_eq!(sink.data, [first, second].concat());
+        assert_eq!(replies(receiver), [CRC_REQUEST, ACK, ACK, ACK, ACK]);
+    }
+
+    #[test]
+    fn block_out_of_sequence_cancels() {
+        let mut receiver = receiver(vec![block(1, SHORT_BLOCK, &[1]), block(3, SHORT_BLOCK, &[3])]);
+        let mut sink = MemorySink::default();
+
+        assert!(matches!(receiver.receive_xmodem(&mut sink), Err(XmodemError::Sequence)));
+        assert_eq!(replies(receiver), [CRC_REQUEST, ACK, CAN, CAN, CAN]);
+    }
+
+    #[test]
+    fn double_can_aborts() {
+        let mut receiver = receiver(vec![block(1, SHORT_BLOCK, &[1]), vec![CAN, CAN]]);
+        let mut sink = MemorySink::default();
+
+        assert!(matches!(receiver.receive_xmodem(&mut sink), Err(XmodemError::Cancelled)));
+        assert_eq!(sink.finished, 0);
+    }
+
+    #[test]
+    fn single_can_is_line_noise() {
+        let mut receiver = receiver(vec![vec![CAN], block(1, SHORT_BLOCK, &[1]), vec![EOT]]);
+        let mut sink = MemorySink::default();
+
+        assert_eq!(receiver.receive_xmodem(&mut sink).unwrap(), SHORT_BLOCK as u32);
+    }
+
+    #[test]
+    fn silent_sender_times_out() {
+        let mut receiver = receiver(vec![]);
+        let mut sink = MemorySink::default();
+
+        assert!(matches!(receiver.receive_xmodem(&mut sink), Err(XmodemError::Timeout)));
+        assert_eq!(replies(receiver), [CRC_REQUEST, CRC_REQUEST, CRC_REQUEST, CAN, CAN, CAN]);
+    }
+
+    #[test]
+    fn ymodem_batch_is_cut_to_size() {
+        let data = pattern(200, 3);
+        let mut receiver = receiver(vec![
+            block(0, SHORT_BLOCK, b"image.bin\x00200 14633151337 100644"),
+            block(1, SHORT_BLOCK, &data[..128]),
+            block(2, SHORT_BLOCK, &data[128..]),
+            vec![EOT],
+            block(0, SHORT_BLOCK, &[0; SHORT_BLOCK]),
+        ]);
+        let mut sink = MemorySink::default();
+
+        assert_eq!(receiver.receive_ymodem(&mut sink).unwrap(), 200);
+        assert_eq!(sink.data, data);
+        assert_eq!(sink.files, [("image.bin".to_string(), Some(200))]);
+        assert_eq!(sink.finished, 1);
+        assert_eq!(
+            replies(receiver),
+            [CRC_REQUEST, ACK, CRC_REQUEST, ACK, ACK, ACK, CRC_REQUEST, ACK]
+        );
+    }
+
+    #[test]
+    fn parses_ymodem_headers() {
+        assert_eq!(parse_header(b"a.bin\x001024 0 0\x00\x00"), Some(("a.bin", Some(1024))));
+        assert_eq!(parse_header(b"a.bin\x00\x00"), Some(("a.bin", None)));
+        assert_eq!(parse_header(b"a.bin\x0042"), Some(("a.bin", Some(42))));
+        assert_eq!(parse_header(&[0; SHORT_BLOCK]), Some(("", None)));
+        assert_eq!(parse_header(b"no terminator"), None);
+        assert_eq!(parse_header(b"\xFF\x00"), None);
+    }
+}